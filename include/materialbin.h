@@ -0,0 +1,122 @@
+/* C interface of the materialbin static library, built with the `ffi` feature.
+ * Keep in sync with src/cffi.rs */
+#ifndef MATERIALBIN_H
+#define MATERIALBIN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define MATERIALBIN_OK 0
+#define MATERIALBIN_ERR_PARSE -1
+#define MATERIALBIN_ERR_WRITE -2
+#define MATERIALBIN_ERR_INVALID_ARGUMENT -3
+#define MATERIALBIN_ERR_NOT_FOUND -4
+/* The library panicked and recovered, see materialbin_last_error.
+ * Null pointer arguments are reported as MATERIALBIN_ERR_INVALID_ARGUMENT */
+#define MATERIALBIN_ERR_PANIC -5
+/* Converted, but uniform overrides the target version can't store were dropped,
+ * materialbin_last_error lists them */
+#define MATERIALBIN_LOST_OVERRIDES 1
+
+/* Material format versions, named after the first game release using them */
+typedef enum MaterialbinVersion {
+    /* Detect the version when reading, keep it when converting */
+    MATERIALBIN_VERSION_AUTO = -1,
+    MATERIALBIN_VERSION_1_18_30 = 0,
+    MATERIALBIN_VERSION_1_19_60 = 1,
+    MATERIALBIN_VERSION_1_20_80 = 2,
+    MATERIALBIN_VERSION_1_21_20 = 3,
+    MATERIALBIN_VERSION_1_21_110 = 4,
+    MATERIALBIN_VERSION_26_0_24 = 5,
+    MATERIALBIN_VERSION_26_10_20 = 6,
+} MaterialbinVersion;
+
+/* Numbering of ShaderStage */
+typedef enum MaterialbinShaderStage {
+    MATERIALBIN_STAGE_VERTEX = 0,
+    MATERIALBIN_STAGE_FRAGMENT = 1,
+    MATERIALBIN_STAGE_COMPUTE = 2,
+    MATERIALBIN_STAGE_UNKNOWN = 3,
+} MaterialbinShaderStage;
+
+/* Numbering of ShaderCodePlatform, the platform ID of 1.21.20 and newer. The C API
+ * uses it for every version, whatever ID the material stores the platform as */
+typedef enum MaterialbinPlatform {
+    MATERIALBIN_PLATFORM_DIRECT3D_SM40 = 0,
+    MATERIALBIN_PLATFORM_DIRECT3D_SM50 = 1,
+    MATERIALBIN_PLATFORM_DIRECT3D_SM60 = 2,
+    MATERIALBIN_PLATFORM_DIRECT3D_SM65 = 3,
+    MATERIALBIN_PLATFORM_DIRECT3D_XB1 = 4,
+    MATERIALBIN_PLATFORM_DIRECT3D_XBX = 5,
+    MATERIALBIN_PLATFORM_GLSL_120 = 6,
+    MATERIALBIN_PLATFORM_GLSL_430 = 7,
+    MATERIALBIN_PLATFORM_ESSL_100 = 8,
+    MATERIALBIN_PLATFORM_ESSL_300 = 9,
+    MATERIALBIN_PLATFORM_ESSL_310 = 10,
+    MATERIALBIN_PLATFORM_METAL = 11,
+    MATERIALBIN_PLATFORM_VULKAN = 12,
+    MATERIALBIN_PLATFORM_NVN = 13,
+    MATERIALBIN_PLATFORM_PSSL = 14,
+} MaterialbinPlatform;
+
+/* Memory owned by the library, release it with free_buf */
+typedef struct Buffer {
+    uint8_t *data;
+    size_t len;
+} Buffer;
+
+/* Replacement source for patch_material */
+typedef struct MaterialbinPatchRule {
+    /* Pass to patch, every pass when NULL */
+    const char *pass;
+    int stage;
+    /* A MaterialbinPlatform, for every version */
+    int platform;
+    const uint8_t *source;
+    size_t source_len;
+} MaterialbinPatchRule;
+
+/* Opaque parsed material, release it with materialbin_free */
+typedef struct MaterialbinMaterial MaterialbinMaterial;
+
+/* Convert a material to 1.21.20, dropping uniform overrides it can't store */
+int update_file(size_t in_length, const uint8_t *in_buffer, Buffer *out_buffer);
+/* Convert a material to target_version, out_detected_version may be NULL.
+ * The input is read as the newest version that parses it, failing to write that
+ * returns MATERIALBIN_ERR_WRITE with the reason in materialbin_last_error.
+ * Dropping uniform overrides the target can't store returns MATERIALBIN_LOST_OVERRIDES */
+int update_file_ex(size_t in_length, const uint8_t *in_buffer, int target_version,
+                   Buffer *out_buffer, int *out_detected_version);
+void free_buf(Buffer buf);
+/* Replace the shader source of matching shader codes, keeping the version.
+ * Returns the number of patched shader codes or a negative error code */
+int patch_material(size_t in_length, const uint8_t *in_buffer,
+                   const MaterialbinPatchRule *rules, size_t rule_count, Buffer *out_buffer);
+
+/* Message of the last failing call on this thread */
+const char *materialbin_last_error(void);
+
+int materialbin_parse(size_t in_length, const uint8_t *in_buffer, int version,
+                      MaterialbinMaterial **out_handle);
+void materialbin_free(MaterialbinMaterial *handle);
+int materialbin_version(const MaterialbinMaterial *handle);
+int materialbin_name(const MaterialbinMaterial *handle, Buffer *out_buffer);
+int materialbin_pass_count(const MaterialbinMaterial *handle);
+int materialbin_pass_name(const MaterialbinMaterial *handle, size_t pass_index,
+                          Buffer *out_buffer);
+int materialbin_variant_count(const MaterialbinMaterial *handle, size_t pass_index);
+/* stage is a MaterialbinShaderStage and platform a MaterialbinPlatform */
+int materialbin_replace_shader(MaterialbinMaterial *handle, size_t pass_index,
+                               size_t variant_index, int stage, int platform,
+                               size_t data_length, const uint8_t *data);
+int materialbin_write(const MaterialbinMaterial *handle, int version, Buffer *out_buffer);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
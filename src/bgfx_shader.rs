@@ -1,11 +1,15 @@
-use byteorder::{LittleEndian, WriteBytesExt};
+use alloc::{borrow::ToOwned, format, string::String, vec::Vec};
+use byteorder::LittleEndian;
 use scroll::{
     ctx::{StrCtx, TryFromCtx},
     Pread, LE,
 };
-use std::io::Write;
 
-use crate::{MyError, WriteError};
+use crate::{
+    common::{checked_len, serialized_size},
+    ByteWrite, MyError, WriteError,
+};
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
 pub struct BgfxShader {
     pub magic: u32,
     pub hash: u32,
@@ -26,9 +30,9 @@ impl<'a> TryFromCtx<'a> for BgfxShader {
             .collect();
         let code_len: u32 = input.gread_with(offset, LE)?;
         let code_len: usize = code_len.try_into().map_err(|e| {
-            scroll::Error::Custom(
-                format!("Code len: {code_len} does not fit in usize, error: {e}").into(),
-            )
+            MyError::custom(format!(
+                "Code len: {code_len} does not fit in usize, error: {e}"
+            ))
         })?;
         let code = input.gread_with::<&[u8]>(offset, code_len)?.to_vec();
         let _dumbbyte: u8 = input.gread(offset)?;
@@ -58,21 +62,42 @@ impl<'a> TryFromCtx<'a> for BgfxShader {
     }
 }
 impl BgfxShader {
+    /// Number of bytes `write` produces
+    pub fn serialized_size(&self) -> Result<usize, WriteError> {
+        serialized_size(|w| self.write_unchecked(w))
+    }
+    /// Write the shader, validated before the first byte reaches `writer` like
+    /// [`crate::CompiledMaterialDefinition::write`]
     pub fn write<W>(&self, writer: &mut W) -> Result<(), WriteError>
     where
-        W: Write,
+        W: ByteWrite,
+    {
+        self.serialized_size()?;
+        self.write_unchecked(writer)
+    }
+    /// Write the shader into a buffer of its exact size
+    pub fn write_vec(&self) -> Result<Vec<u8>, WriteError> {
+        let mut output = Vec::with_capacity(self.serialized_size()?);
+        self.write_unchecked(&mut output)?;
+        Ok(output)
+    }
+    fn write_unchecked<W>(&self, writer: &mut W) -> Result<(), WriteError>
+    where
+        W: ByteWrite,
     {
         writer.write_u32::<LittleEndian>(self.magic)?;
         writer.write_u32::<LittleEndian>(self.hash)?;
-        writer.write_u16::<LittleEndian>(self.uniforms.len().try_into()?)?;
-        for uniform in self.uniforms.iter() {
-            uniform.write(writer)?;
+        writer.write_u16::<LittleEndian>(checked_len(self.uniforms.len(), "uniforms")?)?;
+        for (index, uniform) in self.uniforms.iter().enumerate() {
+            uniform
+                .write(writer)
+                .map_err(|e| e.at(format_args!("uniforms[{index}]")))?;
         }
-        writer.write_u32::<LittleEndian>(self.code.len().try_into()?)?;
+        writer.write_u32::<LittleEndian>(checked_len(self.code.len(), "code")?)?;
         writer.write_all(&self.code)?;
         writer.write_u8(0)?;
         if let Some(attrs) = &self.attributes {
-            writer.write_u8(attrs.len() as u8)?;
+            writer.write_u8(checked_len(attrs.len(), "attributes")?)?;
             for attr in attrs {
                 writer.write_u16::<LittleEndian>(*attr)?;
             }
@@ -83,6 +108,7 @@ impl BgfxShader {
         Ok(())
     }
 }
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
 pub struct Uniform {
     pub name: String,
     pub utype: u8,
@@ -116,11 +142,15 @@ impl<'a> TryFromCtx<'a> for Uniform {
     }
 }
 impl Uniform {
+    /// Number of bytes `write` produces
+    pub fn serialized_size(&self) -> Result<usize, WriteError> {
+        serialized_size(|w| self.write(w))
+    }
     pub fn write<W>(&self, writer: &mut W) -> Result<(), WriteError>
     where
-        W: Write,
+        W: ByteWrite,
     {
-        writer.write_u8(self.name.len().try_into()?)?;
+        writer.write_u8(checked_len(self.name.len(), "name")?)?;
         writer.write_all(self.name.as_bytes())?;
         writer.write_u8(self.utype)?;
         writer.write_u8(self.num)?;
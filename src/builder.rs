@@ -0,0 +1,437 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Display;
+
+use crate::{
+    diff::flag_set_key,
+    pass::{
+        BlendMode, Pass, PlatformShaderStage, ShaderCode, ShaderCodePlatform, ShaderStage, Variant,
+    },
+    property_field::PropertyField,
+    sampler_definition::{
+        CustomTypeInfo, Precision, SamplerAccess, SamplerDefinition, SamplerType,
+    },
+    uniform_override::UniformOverride,
+    CompiledMaterialDefinition, EncryptionVariant, IndexMap, MinecraftVersion, BUILTINS_NAME,
+};
+
+#[derive(Debug)]
+pub enum BuildError {
+    TooMany {
+        what: String,
+        count: usize,
+        max: usize,
+    },
+    Duplicate(String),
+    Incompatible(String),
+    Invalid(String),
+}
+impl core::error::Error for BuildError {}
+impl Display for BuildError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::TooMany { what, count, max } => {
+                write!(f, "Too many {what}: {count}, at most {max} are allowed")
+            }
+            Self::Duplicate(info) => write!(f, "Duplicate entry: {info}"),
+            Self::Incompatible(info) => write!(f, "Incompatible with target version: {info}"),
+            Self::Invalid(info) => write!(f, "Invalid material: {info}"),
+        }
+    }
+}
+fn check_count(what: impl Into<String>, count: usize, max: usize) -> Result<(), BuildError> {
+    if count > max {
+        return Err(BuildError::TooMany {
+            what: what.into(),
+            count,
+            max,
+        });
+    }
+    Ok(())
+}
+
+/// Builds a [`CompiledMaterialDefinition`] for a target version
+/// ```no_run
+/// # use materialbin::builder::*;
+/// let material = MaterialBuilder::new("MyMat")
+///     .parent("Core/Builtins")
+///     .sampler("s_MatTexture", SamplerBuilder::new(0))
+///     .pass("Opaque", PassBuilder::new().variant(VariantBuilder::new()))
+///     .build();
+/// ```
+#[derive(Debug)]
+pub struct MaterialBuilder {
+    version: MinecraftVersion,
+    name: String,
+    parent_name: Option<String>,
+    samplers: Vec<(String, SamplerBuilder)>,
+    property_fields: Vec<(String, PropertyField)>,
+    uniform_overrides: Vec<(String, UniformOverride)>,
+    passes: Vec<(String, PassBuilder)>,
+}
+impl MaterialBuilder {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            version: MinecraftVersion::default(),
+            name: name.into(),
+            parent_name: None,
+            samplers: Vec::new(),
+            property_fields: Vec::new(),
+            uniform_overrides: Vec::new(),
+            passes: Vec::new(),
+        }
+    }
+    pub fn version(mut self, version: MinecraftVersion) -> Self {
+        self.version = version;
+        self
+    }
+    pub fn parent(mut self, parent_name: impl Into<String>) -> Self {
+        self.parent_name = Some(parent_name.into());
+        self
+    }
+    pub fn sampler(mut self, name: impl Into<String>, sampler: SamplerBuilder) -> Self {
+        self.samplers.push((name.into(), sampler));
+        self
+    }
+    pub fn property(mut self, name: impl Into<String>, property_field: PropertyField) -> Self {
+        self.property_fields.push((name.into(), property_field));
+        self
+    }
+    pub fn uniform_override(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<UniformOverride>,
+    ) -> Self {
+        self.uniform_overrides.push((name.into(), value.into()));
+        self
+    }
+    pub fn pass(mut self, name: impl Into<String>, pass: PassBuilder) -> Self {
+        self.passes.push((name.into(), pass));
+        self
+    }
+    pub fn build(self) -> Result<CompiledMaterialDefinition, BuildError> {
+        let version = self.version;
+        if self.name.is_empty() {
+            return Err(BuildError::Invalid("Material name is empty".to_string()));
+        }
+        check_count("sampler definitions", self.samplers.len(), u8::MAX.into())?;
+        check_count(
+            "property fields",
+            self.property_fields.len(),
+            u16::MAX.into(),
+        )?;
+        check_count("passes", self.passes.len(), u16::MAX.into())?;
+
+        let mut sampler_definitions: IndexMap<String, SamplerDefinition> =
+            IndexMap::with_capacity_and_hasher(self.samplers.len(), Default::default());
+        for (name, sampler) in self.samplers {
+            let sampler = sampler.build(version)?;
+            if let Some((other, _)) = sampler_definitions
+                .iter()
+                .find(|(_, s)| s.reg == sampler.reg)
+            {
+                return Err(BuildError::Duplicate(format!(
+                    "Samplers {other} and {name} share register {}",
+                    sampler.reg
+                )));
+            }
+            insert_unique(&mut sampler_definitions, "sampler", name, sampler)?;
+        }
+        let mut property_fields =
+            IndexMap::with_capacity_and_hasher(self.property_fields.len(), Default::default());
+        for (name, property_field) in self.property_fields {
+            insert_unique(&mut property_fields, "property field", name, property_field)?;
+        }
+        let uniform_overrides = if version >= MinecraftVersion::V1_21_110
+            && self.name != BUILTINS_NAME
+        {
+            check_count(
+                "uniform overrides",
+                self.uniform_overrides.len(),
+                u16::MAX.into(),
+            )?;
+            let mut overrides = IndexMap::with_capacity_and_hasher(
+                self.uniform_overrides.len(),
+                Default::default(),
+            );
+            for (name, value) in self.uniform_overrides {
+                insert_unique(&mut overrides, "uniform override", name, value)?;
+            }
+            Some(overrides)
+        } else if !self.uniform_overrides.is_empty() {
+            return Err(BuildError::Incompatible(format!(
+                "Uniform overrides need 1.21.110+ and a material other than Core/Builtins, target is {version}"
+            )));
+        } else {
+            None
+        };
+        let mut passes = IndexMap::with_capacity_and_hasher(self.passes.len(), Default::default());
+        for (name, pass) in self.passes {
+            let pass = pass
+                .build(version)
+                .map_err(|e| BuildError::Invalid(format!("Pass {name}: {e}")))?;
+            insert_unique(&mut passes, "pass", name, pass)?;
+        }
+        Ok(CompiledMaterialDefinition {
+            version: version.header_versions()[0],
+            encryption_variant: EncryptionVariant::None,
+            name: self.name,
+            parent_name: self.parent_name,
+            sampler_definitions,
+            property_fields,
+            uniform_overrides,
+            passes,
+        })
+    }
+}
+fn insert_unique<V>(
+    map: &mut IndexMap<String, V>,
+    what: &str,
+    name: String,
+    value: V,
+) -> Result<(), BuildError> {
+    if map.contains_key(&name) {
+        return Err(BuildError::Duplicate(format!("{what} {name}")));
+    }
+    map.insert(name, value);
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct SamplerBuilder {
+    sampler: SamplerDefinition,
+}
+impl SamplerBuilder {
+    pub fn new(reg: u16) -> Self {
+        Self {
+            sampler: SamplerDefinition {
+                reg,
+                access: SamplerAccess::Read,
+                precision: Precision::Low,
+                allow_unordered_access: 0,
+                sampler_type: SamplerType::Type2D,
+                texture_format: String::new(),
+                unknown_int: 0,
+                unknown_byte: 0,
+                sampler_state: None,
+                default_texture: None,
+                unknown_string: None,
+                custom_type_info: None,
+            },
+        }
+    }
+    pub fn access(mut self, access: SamplerAccess) -> Self {
+        self.sampler.access = access;
+        self
+    }
+    pub fn precision(mut self, precision: Precision) -> Self {
+        self.sampler.precision = precision;
+        self
+    }
+    pub fn sampler_type(mut self, sampler_type: SamplerType) -> Self {
+        self.sampler.sampler_type = sampler_type;
+        self
+    }
+    pub fn texture_format(mut self, texture_format: impl Into<String>) -> Self {
+        self.sampler.texture_format = texture_format.into();
+        self
+    }
+    pub fn sampler_state(mut self, sampler_state: u8) -> Self {
+        self.sampler.sampler_state = Some(sampler_state);
+        self
+    }
+    pub fn default_texture(mut self, default_texture: impl Into<String>) -> Self {
+        self.sampler.default_texture = Some(default_texture.into());
+        self
+    }
+    pub fn custom_type(mut self, name: impl Into<String>, size: u32) -> Self {
+        self.sampler.custom_type_info = Some(CustomTypeInfo {
+            name: name.into(),
+            size,
+        });
+        self
+    }
+    pub fn build(self, version: MinecraftVersion) -> Result<SamplerDefinition, BuildError> {
+        let mut sampler = self.sampler;
+        if version == MinecraftVersion::V1_18_30 {
+            // 1.18.30 stores the register as a byte and mirrors it into the unknown byte
+            sampler.unknown_byte = sampler.reg.try_into().map_err(|_| {
+                BuildError::Incompatible(format!(
+                    "Sampler register {} does not fit in a byte on {version}",
+                    sampler.reg
+                ))
+            })?;
+        }
+        if version < MinecraftVersion::V1_21_20 {
+            if sampler.sampler_type == SamplerType::TypeSamplerCubeArray {
+                return Err(BuildError::Incompatible(format!(
+                    "Sampler cube arrays are not supported on {version}"
+                )));
+            }
+            if sampler.sampler_state.is_some() {
+                return Err(BuildError::Incompatible(format!(
+                    "Sampler state is not supported on {version}"
+                )));
+            }
+        }
+        Ok(sampler)
+    }
+}
+
+#[derive(Debug)]
+pub struct PassBuilder {
+    /// Derived from the shader codes for the target version when not set
+    bitset: Option<String>,
+    fallback: String,
+    default_blendmode: Option<BlendMode>,
+    default_flag_values: IndexMap<String, String>,
+    framebuffer_binding: Option<u32>,
+    variants: Vec<VariantBuilder>,
+}
+impl Default for PassBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl PassBuilder {
+    pub fn new() -> Self {
+        Self {
+            bitset: None,
+            fallback: String::new(),
+            default_blendmode: None,
+            default_flag_values: IndexMap::default(),
+            framebuffer_binding: None,
+            variants: Vec::new(),
+        }
+    }
+    pub fn bitset(mut self, bitset: impl Into<String>) -> Self {
+        self.bitset = Some(bitset.into());
+        self
+    }
+    pub fn fallback(mut self, fallback: impl Into<String>) -> Self {
+        self.fallback = fallback.into();
+        self
+    }
+    pub fn blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.default_blendmode = Some(blend_mode);
+        self
+    }
+    pub fn default_flag(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.default_flag_values.insert(name.into(), value.into());
+        self
+    }
+    pub fn framebuffer_binding(mut self, binding: u32) -> Self {
+        self.framebuffer_binding = Some(binding);
+        self
+    }
+    pub fn variant(mut self, variant: VariantBuilder) -> Self {
+        self.variants.push(variant);
+        self
+    }
+    pub fn build(self, version: MinecraftVersion) -> Result<Pass, BuildError> {
+        if self.bitset.as_deref() == Some("") {
+            return Err(BuildError::Invalid("Bitset string is empty".to_string()));
+        }
+        check_count(
+            "default flag values",
+            self.default_flag_values.len(),
+            u16::MAX.into(),
+        )?;
+        check_count("variants", self.variants.len(), u16::MAX.into())?;
+        let framebuffer_binding = if version >= MinecraftVersion::V26_0_24 {
+            Some(self.framebuffer_binding.unwrap_or(0))
+        } else if self.framebuffer_binding.is_some() {
+            return Err(BuildError::Incompatible(format!(
+                "Framebuffer bindings need 26.0.24 or newer, target is {version}"
+            )));
+        } else {
+            None
+        };
+        let mut variants: Vec<Variant> = Vec::with_capacity(self.variants.len());
+        for variant in self.variants {
+            let variant = variant.build()?;
+            let key = flag_set_key(&variant.flags);
+            if let Some(flag) = variant
+                .flags
+                .keys()
+                .find(|flag| !self.default_flag_values.contains_key(*flag))
+            {
+                return Err(BuildError::Invalid(format!(
+                    "Variant [{key}] uses flag {flag} which has no default value"
+                )));
+            }
+            if variants.iter().any(|v| flag_set_key(&v.flags) == key) {
+                return Err(BuildError::Duplicate(format!("variant [{key}]")));
+            }
+            variants.push(variant);
+        }
+        let mut pass = Pass {
+            bitset: self.bitset.unwrap_or_default(),
+            fallback: self.fallback,
+            default_blendmode: self.default_blendmode,
+            default_flag_values: self.default_flag_values,
+            framebuffer_binding,
+            variants,
+        };
+        if pass.bitset.is_empty() {
+            pass.bitset = pass.platform_bitset(version);
+        }
+        Ok(pass)
+    }
+}
+
+#[derive(Debug)]
+pub struct VariantBuilder {
+    variant: Variant,
+}
+impl Default for VariantBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl VariantBuilder {
+    pub fn new() -> Self {
+        Self {
+            variant: Variant {
+                is_supported: true,
+                flags: IndexMap::default(),
+                shader_codes: IndexMap::default(),
+            },
+        }
+    }
+    pub fn supported(mut self, is_supported: bool) -> Self {
+        self.variant.is_supported = is_supported;
+        self
+    }
+    pub fn flag(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.variant.flags.insert(name.into(), value.into());
+        self
+    }
+    pub fn shader_code(
+        mut self,
+        stage: ShaderStage,
+        platform: ShaderCodePlatform,
+        code: ShaderCode,
+    ) -> Self {
+        let stage = PlatformShaderStage {
+            stage_name: stage.to_string(),
+            platform_name: platform.to_string(),
+            stage,
+            platform,
+        };
+        self.variant.shader_codes.insert(stage, code);
+        self
+    }
+    pub fn build(self) -> Result<Variant, BuildError> {
+        check_count("flags", self.variant.flags.len(), u16::MAX.into())?;
+        check_count(
+            "shader codes",
+            self.variant.shader_codes.len(),
+            u16::MAX.into(),
+        )?;
+        Ok(self.variant)
+    }
+}
@@ -1,16 +1,179 @@
 use core::slice;
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    fmt::Display,
+    panic::{self, AssertUnwindSafe},
+};
+
+use libc::{c_char, c_int};
 use scroll::Pread;
 
-use crate::{CompiledMaterialDefinition, ALL_VERSIONS};
+use crate::{
+    pass::{ShaderCodePlatform, ShaderStage},
+    patch::{PatchError, ShaderPatch},
+    uniform_override::lost_overrides_message,
+    CompiledMaterialDefinition, MinecraftVersion, ALL_VERSIONS,
+};
+
+pub const MATERIALBIN_OK: c_int = 0;
+pub const MATERIALBIN_ERR_PARSE: c_int = -1;
+pub const MATERIALBIN_ERR_WRITE: c_int = -2;
+pub const MATERIALBIN_ERR_INVALID_ARGUMENT: c_int = -3;
+pub const MATERIALBIN_ERR_NOT_FOUND: c_int = -4;
+/// The library panicked, the message is in `materialbin_last_error`
+pub const MATERIALBIN_ERR_PANIC: c_int = -5;
+/// Converted, but uniform overrides the target version can't store were dropped.
+/// They are listed in `materialbin_last_error`
+pub const MATERIALBIN_LOST_OVERRIDES: c_int = 1;
+
+/// Version code asking the parser to try every known version
+pub const MATERIALBIN_VERSION_AUTO: c_int = -1;
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 struct Buffer {
     data: *mut u8,
     len: usize,
 }
+impl Buffer {
+    fn from_vec(output: Vec<u8>) -> Self {
+        let mut boxed = output.into_boxed_slice();
+        let bufdata = Buffer {
+            data: boxed.as_mut_ptr(),
+            len: boxed.len(),
+        };
+        std::mem::forget(boxed);
+        bufdata
+    }
+}
+
+/// One replacement for `patch_material`
+#[repr(C)]
+struct PatchRule {
+    /// Nul terminated pass name, every pass when null
+    pass: *const c_char,
+    stage: c_int,
+    platform: c_int,
+    source: *const u8,
+    source_len: usize,
+}
+impl PatchRule {
+    /// # Safety
+    /// - Pointers in the rule are valid
+    unsafe fn to_patch(&self) -> Result<ShaderPatch, String> {
+        let pass = if self.pass.is_null() {
+            None
+        } else {
+            let name = unsafe { CStr::from_ptr(self.pass) };
+            Some(name.to_str().map_err(|e| e.to_string())?.to_string())
+        };
+        let byte = |code: c_int| u8::try_from(code).map(|b| [b]).unwrap_or([u8::MAX]);
+        let stage: ShaderStage = byte(self.stage)
+            .pread(0)
+            .map_err(|_| format!("Unknown shader stage: {}", self.stage))?;
+        let platform = usize::try_from(self.platform)
+            .ok()
+            .and_then(|index| ShaderCodePlatform::ALL.get(index).copied())
+            .ok_or_else(|| format!("Unknown shader platform: {}", self.platform))?;
+        if self.source.is_null() {
+            return Err("source is null".to_string());
+        }
+        let source = unsafe { slice::from_raw_parts(self.source, self.source_len) };
+        Ok(ShaderPatch {
+            pass,
+            stage,
+            platform,
+            source: source.to_vec(),
+        })
+    }
+}
+
+/// Opaque material handle given out to C
+struct Material {
+    definition: CompiledMaterialDefinition,
+    version: MinecraftVersion,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+fn set_last_error(error: impl Display) {
+    let message = CString::new(error.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+fn fail(code: c_int, error: impl Display) -> c_int {
+    set_last_error(error);
+    code
+}
+
+/// Run the body of an export, turning panics into `MATERIALBIN_ERR_PANIC`
+/// so they never unwind into C
+fn guard(body: impl FnOnce() -> c_int) -> c_int {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(code) => code,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown cause");
+            fail(MATERIALBIN_ERR_PANIC, format!("Internal panic: {message}"))
+        }
+    }
+}
+macro_rules! non_null {
+    ($($ptr:ident),+) => {
+        $(
+            if $ptr.is_null() {
+                return fail(
+                    MATERIALBIN_ERR_INVALID_ARGUMENT,
+                    concat!(stringify!($ptr), " is null"),
+                );
+            }
+        )+
+    };
+}
+
+fn version_from_c(version: c_int) -> Option<MinecraftVersion> {
+    ALL_VERSIONS.get(usize::try_from(version).ok()?).copied()
+}
+fn version_to_c(version: MinecraftVersion) -> c_int {
+    ALL_VERSIONS
+        .iter()
+        .position(|v| *v == version)
+        .map_or(MATERIALBIN_ERR_NOT_FOUND, |i| i as c_int)
+}
+fn parse(
+    data: &[u8],
+    version: c_int,
+) -> Result<(CompiledMaterialDefinition, MinecraftVersion), c_int> {
+    if version == MATERIALBIN_VERSION_AUTO {
+        let mut last_error = None;
+        for version in ALL_VERSIONS.into_iter().rev() {
+            match data.pread_with::<CompiledMaterialDefinition>(0, version) {
+                Ok(parsed) => return Ok((parsed, version)),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        return Err(match last_error {
+            Some(e) => fail(MATERIALBIN_ERR_PARSE, e),
+            None => fail(MATERIALBIN_ERR_PARSE, "No version could parse the material"),
+        });
+    }
+    let Some(version) = version_from_c(version) else {
+        return Err(fail(
+            MATERIALBIN_ERR_INVALID_ARGUMENT,
+            format!("Unknown version code: {version}"),
+        ));
+    };
+    data.pread_with(0, version)
+        .map(|parsed| (parsed, version))
+        .map_err(|e| fail(MATERIALBIN_ERR_PARSE, e))
+}
 
 #[no_mangle]
-/// Update a material file
+/// Update a material file to 1.21.20, dropping the uniform overrides it can't store
 /// # Safety
 /// - Input pointer and length are valid
 /// - You free the output later
@@ -19,33 +182,302 @@ extern "C" fn update_file(
     in_buffer: *const u8,
     out_buffer: *mut Buffer,
 ) -> libc::c_int {
-    let slice = unsafe { slice::from_raw_parts(in_buffer, in_length) };
-    let mut output = Vec::with_capacity(slice.len());
-    for version in ALL_VERSIONS.into_iter().rev() {
-        if let Ok(parsed) = slice.pread_with::<CompiledMaterialDefinition>(0, version) {
-            if parsed
-                .write(&mut output, crate::MinecraftVersion::V1_21_20)
-                .is_err()
-            {
-                continue;
-            }
-            let mut boxed = output.into_boxed_slice();
-            let bufdata = Buffer {
-                data: boxed.as_mut_ptr(),
-                len: boxed.len(),
-            };
-            unsafe { *out_buffer = bufdata };
-            std::mem::forget(boxed);
-            return 0;
-        }
+    let target = version_to_c(MinecraftVersion::V1_21_20);
+    match update_file_ex(
+        in_length,
+        in_buffer,
+        target,
+        out_buffer,
+        std::ptr::null_mut(),
+    ) {
+        MATERIALBIN_LOST_OVERRIDES => MATERIALBIN_OK,
+        code => code,
     }
-    -1
+}
+#[no_mangle]
+/// Update a material file to `target_version`, or keep its version when that is
+/// `MATERIALBIN_VERSION_AUTO`. The version the input was parsed as is stored in
+/// `out_detected_version` unless it is null. Uniform overrides the target can't store
+/// are dropped, returning `MATERIALBIN_LOST_OVERRIDES`
+/// # Safety
+/// - Input pointer and length are valid
+/// - You free the output later
+extern "C" fn update_file_ex(
+    in_length: usize,
+    in_buffer: *const u8,
+    target_version: c_int,
+    out_buffer: *mut Buffer,
+    out_detected_version: *mut c_int,
+) -> c_int {
+    guard(|| {
+        non_null!(in_buffer, out_buffer);
+        let slice = unsafe { slice::from_raw_parts(in_buffer, in_length) };
+        let target = match target_version {
+            MATERIALBIN_VERSION_AUTO => None,
+            code => match version_from_c(code) {
+                Some(version) => Some(version),
+                None => {
+                    return fail(
+                        MATERIALBIN_ERR_INVALID_ARGUMENT,
+                        format!("Unknown version code: {code}"),
+                    )
+                }
+            },
+        };
+        let (mut parsed, version) = match parse(slice, MATERIALBIN_VERSION_AUTO) {
+            Ok(parsed) => parsed,
+            Err(code) => return code,
+        };
+        let target = target.unwrap_or(version);
+        let lost = parsed.inline_uniform_overrides(target, None);
+        let output = match parsed.write_vec(target) {
+            Ok(output) => output,
+            Err(e) => return fail(MATERIALBIN_ERR_WRITE, e),
+        };
+        if !out_detected_version.is_null() {
+            unsafe { *out_detected_version = version_to_c(version) };
+        }
+        unsafe { *out_buffer = Buffer::from_vec(output) };
+        if lost.is_empty() {
+            MATERIALBIN_OK
+        } else {
+            fail(MATERIALBIN_LOST_OVERRIDES, lost_overrides_message(&lost))
+        }
+    })
 }
 #[no_mangle]
 extern "C" fn free_buf(buf: Buffer) {
-    let s = unsafe { std::slice::from_raw_parts_mut(buf.data, buf.len) };
-    let s = s.as_mut_ptr();
-    unsafe {
+    if buf.data.is_null() {
+        return;
+    }
+    let s = std::ptr::slice_from_raw_parts_mut(buf.data, buf.len);
+    let _ = panic::catch_unwind(|| unsafe {
         drop(Box::from_raw(s));
+    });
+}
+
+#[no_mangle]
+/// Message describing the last error on this thread, valid until the next failing call
+extern "C" fn materialbin_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+#[no_mangle]
+/// Parse a material, `version` being a version code or `MATERIALBIN_VERSION_AUTO`
+/// # Safety
+/// - Input pointer and length are valid
+/// - You free the handle later with `materialbin_free`
+extern "C" fn materialbin_parse(
+    in_length: usize,
+    in_buffer: *const u8,
+    version: c_int,
+    out_handle: *mut *mut Material,
+) -> c_int {
+    guard(|| {
+        non_null!(in_buffer, out_handle);
+        let slice = unsafe { slice::from_raw_parts(in_buffer, in_length) };
+        match parse(slice, version) {
+            Ok((definition, version)) => {
+                let handle = Box::new(Material {
+                    definition,
+                    version,
+                });
+                unsafe { *out_handle = Box::into_raw(handle) };
+                MATERIALBIN_OK
+            }
+            Err(code) => code,
+        }
+    })
+}
+#[no_mangle]
+extern "C" fn materialbin_free(handle: *mut Material) {
+    if handle.is_null() {
+        return;
     }
+    let _ = panic::catch_unwind(|| unsafe { drop(Box::from_raw(handle)) });
+}
+#[no_mangle]
+/// Version code the material was parsed with
+extern "C" fn materialbin_version(handle: *const Material) -> c_int {
+    guard(|| {
+        non_null!(handle);
+        let material = unsafe { &*handle };
+        version_to_c(material.version)
+    })
+}
+#[no_mangle]
+extern "C" fn materialbin_name(handle: *const Material, out_buffer: *mut Buffer) -> c_int {
+    guard(|| {
+        non_null!(handle, out_buffer);
+        let material = unsafe { &*handle };
+        let name = material.definition.name.as_bytes().to_vec();
+        unsafe { *out_buffer = Buffer::from_vec(name) };
+        MATERIALBIN_OK
+    })
+}
+#[no_mangle]
+/// Number of passes, or a negative error code
+extern "C" fn materialbin_pass_count(handle: *const Material) -> c_int {
+    guard(|| {
+        non_null!(handle);
+        let material = unsafe { &*handle };
+        material
+            .definition
+            .passes
+            .len()
+            .try_into()
+            .unwrap_or(c_int::MAX)
+    })
+}
+#[no_mangle]
+extern "C" fn materialbin_pass_name(
+    handle: *const Material,
+    pass_index: usize,
+    out_buffer: *mut Buffer,
+) -> c_int {
+    guard(|| {
+        non_null!(handle, out_buffer);
+        let material = unsafe { &*handle };
+        let Some((name, _)) = material.definition.passes.get_index(pass_index) else {
+            return fail(
+                MATERIALBIN_ERR_NOT_FOUND,
+                format!("No pass at index {pass_index}"),
+            );
+        };
+        unsafe { *out_buffer = Buffer::from_vec(name.as_bytes().to_vec()) };
+        MATERIALBIN_OK
+    })
+}
+#[no_mangle]
+/// Number of variants in a pass, or a negative error code
+extern "C" fn materialbin_variant_count(handle: *const Material, pass_index: usize) -> c_int {
+    guard(|| {
+        non_null!(handle);
+        let material = unsafe { &*handle };
+        match material.definition.passes.get_index(pass_index) {
+            Some((_, pass)) => pass.variants.len().try_into().unwrap_or(c_int::MAX),
+            None => fail(
+                MATERIALBIN_ERR_NOT_FOUND,
+                format!("No pass at index {pass_index}"),
+            ),
+        }
+    })
+}
+#[no_mangle]
+/// Replace the bgfx shader blob of one shader code.
+/// `stage` and `platform` use the numbering of `ShaderStage` and [`ShaderCodePlatform::ALL`]
+/// # Safety
+/// - Data pointer and length are valid
+extern "C" fn materialbin_replace_shader(
+    handle: *mut Material,
+    pass_index: usize,
+    variant_index: usize,
+    stage: c_int,
+    platform: c_int,
+    data_length: usize,
+    data: *const u8,
+) -> c_int {
+    guard(|| {
+        non_null!(handle, data);
+        let material = unsafe { &mut *handle };
+        let data = unsafe { slice::from_raw_parts(data, data_length) };
+        let Some(variant) = material
+            .definition
+            .passes
+            .get_index_mut(pass_index)
+            .and_then(|(_, pass)| pass.variants.get_mut(variant_index))
+        else {
+            return fail(
+                MATERIALBIN_ERR_NOT_FOUND,
+                format!("No variant {variant_index} in pass {pass_index}"),
+            );
+        };
+        let code = variant.shader_codes.iter_mut().find(|(key, _)| {
+            c_int::from(key.stage.to_u8()) == stage && c_int::from(key.platform.to_u8()) == platform
+        });
+        match code {
+            Some((_, code)) => {
+                code.bgfx_shader_data = data.to_vec();
+                MATERIALBIN_OK
+            }
+            None => fail(
+                MATERIALBIN_ERR_NOT_FOUND,
+                format!("No shader code for stage {stage} and platform {platform}"),
+            ),
+        }
+    })
+}
+#[no_mangle]
+/// Write the material as the given version code
+/// # Safety
+/// - You free the output later
+extern "C" fn materialbin_write(
+    handle: *const Material,
+    version: c_int,
+    out_buffer: *mut Buffer,
+) -> c_int {
+    guard(|| {
+        non_null!(handle, out_buffer);
+        let material = unsafe { &*handle };
+        let Some(version) = version_from_c(version) else {
+            return fail(
+                MATERIALBIN_ERR_INVALID_ARGUMENT,
+                format!("Unknown version code: {version}"),
+            );
+        };
+        let output = match material.definition.write_vec(version) {
+            Ok(output) => output,
+            Err(e) => return fail(MATERIALBIN_ERR_WRITE, e),
+        };
+        unsafe { *out_buffer = Buffer::from_vec(output) };
+        MATERIALBIN_OK
+    })
+}
+#[no_mangle]
+/// Replace the shader source of every shader code matched by `rules`, keeping
+/// the material version. Returns how many shader codes were patched, or a
+/// negative error code (`MATERIALBIN_ERR_NOT_FOUND` if a rule matched nothing)
+/// # Safety
+/// - Input pointer and length are valid
+/// - `rules` points to `rule_count` valid rules
+/// - You free the output later
+extern "C" fn patch_material(
+    in_length: usize,
+    in_buffer: *const u8,
+    rules: *const PatchRule,
+    rule_count: usize,
+    out_buffer: *mut Buffer,
+) -> c_int {
+    guard(|| {
+        non_null!(in_buffer, rules, out_buffer);
+        let slice = unsafe { slice::from_raw_parts(in_buffer, in_length) };
+        let rules = unsafe { slice::from_raw_parts(rules, rule_count) };
+        let (mut material, version) = match parse(slice, MATERIALBIN_VERSION_AUTO) {
+            Ok(parsed) => parsed,
+            Err(code) => return code,
+        };
+        let mut patches = Vec::with_capacity(rules.len());
+        for (index, rule) in rules.iter().enumerate() {
+            match unsafe { rule.to_patch() } {
+                Ok(patch) => patches.push(patch),
+                Err(e) => {
+                    return fail(
+                        MATERIALBIN_ERR_INVALID_ARGUMENT,
+                        format!("Rule {index}: {e}"),
+                    )
+                }
+            }
+        }
+        let patched = match material.patch_shaders(&patches) {
+            Ok(patched) => patched,
+            Err(e @ PatchError::NoMatch(_)) => return fail(MATERIALBIN_ERR_NOT_FOUND, e),
+            Err(e) => return fail(MATERIALBIN_ERR_WRITE, e),
+        };
+        let output = match material.write_vec(version) {
+            Ok(output) => output,
+            Err(e) => return fail(MATERIALBIN_ERR_WRITE, e),
+        };
+        unsafe { *out_buffer = Buffer::from_vec(output) };
+        patched.try_into().unwrap_or(c_int::MAX)
+    })
 }
@@ -1,7 +1,92 @@
-use crate::WriteError;
-use byteorder::WriteBytesExt;
+use crate::{IndexMap, WriteError};
+use alloc::string::{String, ToString};
+use byteorder::ByteOrder;
+use core::hash::{Hash, Hasher};
 use scroll::{ctx::StrCtx, Pread, LE};
-use std::io::Write;
+
+/// Byte sink materials are written to. Implemented for every `std::io::Write`
+/// with the `std` feature, and for `Vec<u8>` without it
+pub trait ByteWrite {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), WriteError>;
+
+    fn write_u8(&mut self, n: u8) -> Result<(), WriteError> {
+        self.write_all(&[n])
+    }
+    fn write_u16<B: ByteOrder>(&mut self, n: u16) -> Result<(), WriteError> {
+        let mut buf = [0; 2];
+        B::write_u16(&mut buf, n);
+        self.write_all(&buf)
+    }
+    fn write_u32<B: ByteOrder>(&mut self, n: u32) -> Result<(), WriteError> {
+        let mut buf = [0; 4];
+        B::write_u32(&mut buf, n);
+        self.write_all(&buf)
+    }
+    fn write_u64<B: ByteOrder>(&mut self, n: u64) -> Result<(), WriteError> {
+        let mut buf = [0; 8];
+        B::write_u64(&mut buf, n);
+        self.write_all(&buf)
+    }
+}
+#[cfg(feature = "std")]
+impl<W: std::io::Write + ?Sized> ByteWrite for W {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), WriteError> {
+        std::io::Write::write_all(self, buf)?;
+        Ok(())
+    }
+}
+#[cfg(not(feature = "std"))]
+impl ByteWrite for alloc::vec::Vec<u8> {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), WriteError> {
+        self.extend_from_slice(buf);
+        Ok(())
+    }
+}
+#[cfg(not(feature = "std"))]
+impl<W: ByteWrite + ?Sized> ByteWrite for &mut W {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), WriteError> {
+        (**self).write_all(buf)
+    }
+}
+
+/// Writer that only counts bytes, so sizes come from the same code as the writers
+#[derive(Default)]
+pub struct SizeCounter(usize);
+impl ByteWrite for SizeCounter {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), WriteError> {
+        self.0 += buf.len();
+        Ok(())
+    }
+}
+/// Count the bytes `write_fn` writes, failing like it would
+pub fn serialized_size<Fn>(write_fn: Fn) -> Result<usize, WriteError>
+where
+    Fn: FnOnce(&mut SizeCounter) -> Result<(), WriteError>,
+{
+    let mut counter = SizeCounter::default();
+    write_fn(&mut counter)?;
+    Ok(counter.0)
+}
+
+/// 64 bit FNV-1a, used where std's hashers aren't available
+#[derive(Clone, Copy)]
+pub struct FnvHasher(u64);
+impl Default for FnvHasher {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
 pub fn read_bool(buffer: &[u8], offset: &mut usize) -> Result<bool, scroll::Error> {
     let bool_u8: u8 = buffer.gread_with(offset, LE)?;
     Ok(bool_u8 != 0)
@@ -13,21 +98,42 @@ pub fn read_string(buffer: &[u8], offset: &mut usize) -> Result<String, scroll::
 }
 pub fn write_string<W>(string: &str, writer: &mut W) -> Result<(), WriteError>
 where
-    W: Write,
+    W: ByteWrite,
 {
     use byteorder::LE;
-    let len: u32 = string.len().try_into()?;
+    let len: u32 = checked_len(string.len(), "string")?;
     writer.write_u32::<LE>(len)?;
     writer.write_all(string.as_bytes())?;
     Ok(())
 }
+/// Integer types collections and strings are length prefixed with
+pub trait LenPrefix: TryFrom<usize> {
+    const MAX: usize;
+}
+impl LenPrefix for u8 {
+    const MAX: usize = u8::MAX as usize;
+}
+impl LenPrefix for u16 {
+    const MAX: usize = u16::MAX as usize;
+}
+impl LenPrefix for u32 {
+    const MAX: usize = u32::MAX as usize;
+}
+/// Convert a length to its prefix type, reporting `path` when it doesn't fit
+pub fn checked_len<T: LenPrefix>(len: usize, path: &str) -> Result<T, WriteError> {
+    T::try_from(len).map_err(|_| WriteError::Limit {
+        path: path.to_string(),
+        len,
+        max: T::MAX,
+    })
+}
 pub fn optional_write<T, W, Fn, E>(
     output: &mut W,
     option: Option<T>,
     write_fn: Fn,
 ) -> Result<(), WriteError>
 where
-    W: Write,
+    W: ByteWrite,
     Fn: FnOnce(&mut W, T) -> Result<(), E>,
     WriteError: From<E>,
 {
@@ -37,3 +143,112 @@ where
     }
     Ok(())
 }
+/// Hash map entries regardless of order, as `IndexMap` equality ignores order
+pub fn hash_unordered<K, V, H>(map: &IndexMap<K, V>, state: &mut H)
+where
+    K: Hash,
+    V: Hash,
+    H: Hasher,
+{
+    let combined = map
+        .iter()
+        .map(|entry| {
+            let mut hasher = FnvHasher::default();
+            entry.hash(&mut hasher);
+            hasher.finish()
+        })
+        .fold(0u64, u64::wrapping_add);
+    state.write_usize(map.len());
+    state.write_u64(combined);
+}
+/// Implement `Display` and case-insensitive `FromStr` for an enum. A variant holding
+/// the raw value, if named, displays as `Name(raw)` and is never parsed from a string
+macro_rules! enum_names {
+    ($ty:ty, $kind:literal, { $($variant:ident => $name:literal),* $(,)? } $(, $unknown:ident)?) => {
+        impl core::fmt::Display for $ty {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                let name = match self {
+                    $(Self::$variant => $name,)*
+                    $(Self::$unknown(raw) => {
+                        return write!(f, "{}({raw})", stringify!($unknown))
+                    })?
+                };
+                write!(f, "{name}")
+            }
+        }
+        impl core::str::FromStr for $ty {
+            type Err = $crate::ParseEnumError;
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                $(
+                    if s.eq_ignore_ascii_case($name) {
+                        return Ok(Self::$variant);
+                    }
+                )*
+                Err($crate::ParseEnumError::new($kind, s))
+            }
+        }
+    };
+}
+pub(crate) use enum_names;
+
+/// Conversion between an enum and the little endian integer it's stored as, for enums
+/// with a variant holding the raw value, `Unknown(raw)` unless named. Values without a
+/// variant are an error unless [`ParseOptions::lenient`](crate::ParseOptions::lenient)
+/// is set
+macro_rules! raw_enum {
+    ($ty:ty, $raw:ty, $to_raw:ident, $kind:literal, { $($variant:ident = $value:literal),* $(,)? }) => {
+        raw_enum!($ty, $raw, $to_raw, $kind, { $($variant = $value),* }, Unknown);
+    };
+    ($ty:ty, $raw:ty, $to_raw:ident, $kind:literal, { $($variant:ident = $value:literal),* $(,)? }, $other:ident) => {
+        impl $ty {
+            /// Value as stored, the raw variant gives back the value it was read from
+            pub fn $to_raw(self) -> $raw {
+                match self {
+                    $(Self::$variant => $value,)*
+                    Self::$other(raw) => raw,
+                }
+            }
+        }
+        impl<'a> scroll::ctx::TryFromCtx<'a, $crate::ParseOptions> for $ty {
+            type Error = $crate::MyError;
+            fn try_from_ctx(
+                buffer: &'a [u8],
+                options: $crate::ParseOptions,
+            ) -> Result<(Self, usize), Self::Error> {
+                let raw: $raw = scroll::Pread::pread_with(buffer, 0, scroll::LE)?;
+                let value = match raw {
+                    $($value => Self::$variant,)*
+                    raw if options.lenient => Self::$other(raw),
+                    raw => {
+                        return Err($crate::MyError::custom(alloc::format!(
+                            "Invalid {}: {raw}",
+                            $kind
+                        )))
+                    }
+                };
+                Ok((value, core::mem::size_of::<$raw>()))
+            }
+        }
+    };
+}
+pub(crate) use raw_enum;
+/// Strict parsing with a bare `MinecraftVersion`, the context the type took before
+/// [`ParseOptions`](crate::ParseOptions). Types that took no context only implement
+/// `ParseOptions`, whose default is strict
+macro_rules! strict_ctx {
+    ($ty:ty) => {
+        impl<'a> scroll::ctx::TryFromCtx<'a, $crate::MinecraftVersion> for $ty {
+            type Error = $crate::MyError;
+            fn try_from_ctx(
+                buffer: &'a [u8],
+                version: $crate::MinecraftVersion,
+            ) -> Result<(Self, usize), Self::Error> {
+                <Self as scroll::ctx::TryFromCtx<'a, $crate::ParseOptions>>::try_from_ctx(
+                    buffer,
+                    version.into(),
+                )
+            }
+        }
+    };
+}
+pub(crate) use strict_ctx;
@@ -0,0 +1,373 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    fmt::{Debug, Display},
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    common::FnvHasher,
+    pass::{Pass, PlatformShaderStage, ShaderCode, Variant},
+    CompiledMaterialDefinition, IndexMap,
+};
+
+macro_rules! field_changes {
+    ($old:expr, $new:expr, [$($field:ident),* $(,)?]) => {{
+        let mut changes = Vec::new();
+        $(
+            if $old.$field != $new.$field {
+                changes.push(FieldChange::new(stringify!($field), &$old.$field, &$new.$field));
+            }
+        )*
+        changes
+    }};
+}
+
+/// Structural difference between two materials, see [`CompiledMaterialDefinition::diff`]
+#[derive(Debug, Default)]
+pub struct MaterialDiff {
+    pub name: String,
+    pub fields: Vec<FieldChange>,
+    pub sampler_definitions: MapDiff,
+    pub property_fields: MapDiff,
+    pub uniform_overrides: MapDiff,
+    pub passes: MapDiff<PassDiff>,
+}
+impl MaterialDiff {
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+            && self.sampler_definitions.is_empty()
+            && self.property_fields.is_empty()
+            && self.uniform_overrides.is_empty()
+            && self.passes.is_empty()
+    }
+}
+#[derive(Debug, Default)]
+pub struct PassDiff {
+    pub fields: Vec<FieldChange>,
+    /// Variants keyed by their flag set, see [`variant_keys`]
+    pub variants: MapDiff<VariantDiff>,
+}
+#[derive(Debug, Default)]
+pub struct VariantDiff {
+    pub fields: Vec<FieldChange>,
+    /// Shader codes keyed by platform and stage, see [`shader_code_key`]
+    pub shader_codes: MapDiff,
+}
+/// Entries added, removed and changed between two keyed collections
+#[derive(Debug)]
+pub struct MapDiff<C = Vec<FieldChange>> {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<(String, C)>,
+}
+impl<C> Default for MapDiff<C> {
+    fn default() -> Self {
+        Self {
+            added: Vec::new(),
+            removed: Vec::new(),
+            changed: Vec::new(),
+        }
+    }
+}
+impl<C> MapDiff<C> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+#[derive(Debug, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub old: String,
+    pub new: String,
+}
+impl FieldChange {
+    fn new<T: Debug>(field: &'static str, old: &T, new: &T) -> Self {
+        Self {
+            field,
+            old: format!("{old:?}"),
+            new: format!("{new:?}"),
+        }
+    }
+}
+
+impl CompiledMaterialDefinition {
+    /// Compare two materials structurally, matching entries by name
+    /// (or by flag set for variants) instead of by index
+    pub fn diff(&self, other: &Self) -> MaterialDiff {
+        MaterialDiff {
+            name: other.name.clone(),
+            fields: field_changes!(
+                self,
+                other,
+                [version, encryption_variant, name, parent_name]
+            ),
+            sampler_definitions: diff_maps(
+                &by_name(&self.sampler_definitions),
+                &by_name(&other.sampler_definitions),
+                |old, new| {
+                    field_changes!(
+                        old,
+                        new,
+                        [
+                            reg,
+                            access,
+                            precision,
+                            allow_unordered_access,
+                            sampler_type,
+                            texture_format,
+                            unknown_int,
+                            unknown_byte,
+                            sampler_state,
+                            default_texture,
+                            unknown_string,
+                            custom_type_info,
+                        ]
+                    )
+                },
+            ),
+            property_fields: diff_maps(
+                &by_name(&self.property_fields),
+                &by_name(&other.property_fields),
+                |old, new| field_changes!(old, new, [field_type, num, vector_data, matrix_data]),
+            ),
+            uniform_overrides: diff_maps(
+                &self
+                    .uniform_overrides
+                    .as_ref()
+                    .map(by_name)
+                    .unwrap_or_default(),
+                &other
+                    .uniform_overrides
+                    .as_ref()
+                    .map(by_name)
+                    .unwrap_or_default(),
+                |old, new| field_changes!(old, new, [source]),
+            ),
+            passes: diff_maps(&by_name(&self.passes), &by_name(&other.passes), diff_pass),
+        }
+    }
+}
+fn diff_pass(old: &Pass, new: &Pass) -> PassDiff {
+    PassDiff {
+        fields: field_changes!(
+            old,
+            new,
+            [
+                bitset,
+                fallback,
+                default_blendmode,
+                default_flag_values,
+                framebuffer_binding,
+            ]
+        ),
+        variants: diff_maps(
+            &variant_keys(&old.variants),
+            &variant_keys(&new.variants),
+            diff_variant,
+        ),
+    }
+}
+fn diff_variant(old: &Variant, new: &Variant) -> VariantDiff {
+    fn shader_codes(variant: &Variant) -> IndexMap<String, &ShaderCode> {
+        variant
+            .shader_codes
+            .iter()
+            .map(|(stage, code)| (shader_code_key(stage), code))
+            .collect()
+    }
+    VariantDiff {
+        fields: field_changes!(old, new, [is_supported]),
+        shader_codes: diff_maps(&shader_codes(old), &shader_codes(new), diff_shader_code),
+    }
+}
+fn diff_shader_code(old: &ShaderCode, new: &ShaderCode) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+    let input_names = |code: &ShaderCode| code.shader_inputs.keys().cloned().collect::<Vec<_>>();
+    if old.shader_inputs != new.shader_inputs {
+        changes.push(FieldChange::new(
+            "shader_inputs",
+            &input_names(old),
+            &input_names(new),
+        ));
+    }
+    if old.source_hash != new.source_hash {
+        changes.push(FieldChange {
+            field: "source_hash",
+            old: format!("{:016x}", old.source_hash),
+            new: format!("{:016x}", new.source_hash),
+        });
+    }
+    if old.bgfx_shader_data != new.bgfx_shader_data {
+        let describe = |data: &[u8]| format!("{:016x} ({} bytes)", data_hash(data), data.len());
+        changes.push(FieldChange {
+            field: "bgfx_shader_data",
+            old: describe(&old.bgfx_shader_data),
+            new: describe(&new.bgfx_shader_data),
+        });
+    }
+    changes
+}
+fn data_hash(data: &[u8]) -> u64 {
+    let mut hasher = FnvHasher::default();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+/// Key identifying a variant by its flags, independent of flag order
+pub fn flag_set_key(flags: &IndexMap<String, String>) -> String {
+    if flags.is_empty() {
+        return "(no flags)".to_string();
+    }
+    let mut pairs: Vec<String> = flags.iter().map(|(k, v)| format!("{k}={v}")).collect();
+    pairs.sort();
+    pairs.join(", ")
+}
+/// Variants keyed by [`flag_set_key`]. Variants repeating an earlier flag set get
+/// their occurrence appended, `#2` for the second, so none of them is merged away
+pub fn variant_keys(variants: &[Variant]) -> IndexMap<String, &Variant> {
+    let mut keyed = IndexMap::default();
+    for variant in variants {
+        let key = flag_set_key(&variant.flags);
+        let mut unique = key.clone();
+        let mut occurrence = 1;
+        while keyed.contains_key(&unique) {
+            occurrence += 1;
+            unique = format!("{key} #{occurrence}");
+        }
+        keyed.insert(unique, variant);
+    }
+    keyed
+}
+/// Key identifying a shader code by its platform and stage
+pub fn shader_code_key(stage: &PlatformShaderStage) -> String {
+    format!("{} {:?}", stage.platform, stage.stage)
+}
+pub(crate) fn by_name<V>(map: &IndexMap<String, V>) -> IndexMap<String, &V> {
+    map.iter().map(|(k, v)| (k.clone(), v)).collect()
+}
+/// Changes of one entry, entries whose changes come out empty are left out. That
+/// happens when only the order of their own entries differs
+trait Change {
+    fn is_empty(&self) -> bool;
+}
+impl Change for Vec<FieldChange> {
+    fn is_empty(&self) -> bool {
+        <[FieldChange]>::is_empty(self)
+    }
+}
+impl Change for PassDiff {
+    fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.variants.is_empty()
+    }
+}
+impl Change for VariantDiff {
+    fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.shader_codes.is_empty()
+    }
+}
+fn diff_maps<V, C, F>(old: &IndexMap<String, &V>, new: &IndexMap<String, &V>, diff: F) -> MapDiff<C>
+where
+    V: PartialEq,
+    C: Change,
+    F: Fn(&V, &V) -> C,
+{
+    let mut map_diff = MapDiff::default();
+    for (key, old_value) in old {
+        match new.get(key) {
+            Some(new_value) if old_value != new_value => {
+                let changes = diff(old_value, new_value);
+                if !changes.is_empty() {
+                    map_diff.changed.push((key.clone(), changes));
+                }
+            }
+            Some(_) => {}
+            None => map_diff.removed.push(key.clone()),
+        }
+    }
+    map_diff.added = new
+        .keys()
+        .filter(|key| !old.contains_key(*key))
+        .cloned()
+        .collect();
+    map_diff
+}
+
+impl Display for MaterialDiff {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "Material {}", self.name)?;
+        if self.is_empty() {
+            return writeln!(f, "  (no changes)");
+        }
+        write_fields(f, &self.fields, 1)?;
+        write_map(f, "Samplers", &self.sampler_definitions, 1, |f, c, i| {
+            write_fields(f, c, i)
+        })?;
+        write_map(f, "Properties", &self.property_fields, 1, |f, c, i| {
+            write_fields(f, c, i)
+        })?;
+        write_map(
+            f,
+            "Uniform overrides",
+            &self.uniform_overrides,
+            1,
+            |f, c, i| write_fields(f, c, i),
+        )?;
+        write_map(f, "Passes", &self.passes, 1, |f, pass, i| {
+            write_fields(f, &pass.fields, i)?;
+            write_map(f, "Variants", &pass.variants, i, |f, variant, i| {
+                write_fields(f, &variant.fields, i)?;
+                write_map(f, "Shader codes", &variant.shader_codes, i, |f, c, i| {
+                    write_fields(f, c, i)
+                })
+            })
+        })
+    }
+}
+fn write_fields(
+    f: &mut core::fmt::Formatter<'_>,
+    changes: &[FieldChange],
+    indent: usize,
+) -> core::fmt::Result {
+    for change in changes {
+        writeln!(
+            f,
+            "{:indent$}{}: {} -> {}",
+            "",
+            change.field,
+            change.old,
+            change.new,
+            indent = indent * 2
+        )?;
+    }
+    Ok(())
+}
+fn write_map<C, F>(
+    f: &mut core::fmt::Formatter<'_>,
+    title: &str,
+    diff: &MapDiff<C>,
+    indent: usize,
+    write_changed: F,
+) -> core::fmt::Result
+where
+    F: Fn(&mut core::fmt::Formatter<'_>, &C, usize) -> core::fmt::Result,
+{
+    if diff.is_empty() {
+        return Ok(());
+    }
+    let pad = indent * 2;
+    writeln!(f, "{:pad$}{title}:", "")?;
+    for name in &diff.added {
+        writeln!(f, "{:pad$}  + {name}", "")?;
+    }
+    for name in &diff.removed {
+        writeln!(f, "{:pad$}  - {name}", "")?;
+    }
+    for (name, changes) in &diff.changed {
+        writeln!(f, "{:pad$}  ~ {name}", "")?;
+        write_changed(f, changes, indent + 2)?;
+    }
+    Ok(())
+}
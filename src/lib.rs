@@ -1,29 +1,72 @@
-use byteorder::{LittleEndian, WriteBytesExt};
-use indexmap::IndexMap;
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+// The staticlib and cdylib still need a panic handler and allocator without `std`,
+// take them from std on targets that have one
+#[cfg(all(not(feature = "std"), not(target_os = "none")))]
+extern crate std as _;
+
+#[cfg(feature = "backtracing")]
+use alloc::boxed::Box;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use byteorder::LittleEndian;
+use core::{
+    fmt::Display,
+    hash::{Hash, Hasher},
+};
 use pass::Pass;
 use property_field::PropertyField;
 use sampler_definition::SamplerDefinition;
 use scroll::{ctx::TryFromCtx, Pread, LE};
-use std::{
-    backtrace::Backtrace, cmp::Ordering, convert::identity, error::Error, fmt::Display, io::Write,
-};
+#[cfg(feature = "backtracing")]
+use std::backtrace::Backtrace;
+use uniform_override::UniformOverride;
 pub mod bgfx_shader;
+pub mod builder;
 #[cfg(feature = "ffi")]
 mod cffi;
 mod common;
+pub mod diff;
+pub mod material_set;
+pub mod merge;
 pub mod pass;
+pub mod patch;
 pub mod property_field;
+#[cfg(feature = "python")]
+mod python;
+pub mod render_state;
 pub mod sampler_definition;
+pub mod uniform_override;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
-use crate::common::{optional_write, read_bool, read_string, write_string};
-pub const ALL_VERSIONS: [MinecraftVersion; 6] = [
-    // This version causes parsing issues
+pub use crate::common::ByteWrite;
+#[cfg(not(feature = "std"))]
+pub use crate::common::FnvHasher;
+use crate::common::{
+    checked_len, hash_unordered, optional_write, read_bool, read_string, serialized_size,
+    strict_ctx, write_string,
+};
+
+/// Hasher of every map in a material, without `std` there is no random state to seed from
+#[cfg(feature = "std")]
+pub type MapHasher = std::hash::RandomState;
+#[cfg(not(feature = "std"))]
+pub type MapHasher = core::hash::BuildHasherDefault<FnvHasher>;
+pub type IndexMap<K, V> = indexmap::IndexMap<K, V, MapHasher>;
+/// Material holding the builtin uniforms, it never has uniform overrides itself
+pub const BUILTINS_NAME: &str = "Core/Builtins";
+pub const ALL_VERSIONS: [MinecraftVersion; 7] = [
     MinecraftVersion::V1_18_30,
     MinecraftVersion::V1_19_60,
     MinecraftVersion::V1_20_80,
     MinecraftVersion::V1_21_20,
     MinecraftVersion::V1_21_110,
     MinecraftVersion::V26_0_24,
+    MinecraftVersion::V26_10_20,
 ];
 #[derive(Clone, Copy, PartialEq, Eq, Debug, PartialOrd, Ord, Default)]
 pub enum MinecraftVersion {
@@ -36,22 +79,97 @@ pub enum MinecraftVersion {
     V1_21_110,
     #[default]
     V26_0_24,
+    /// Same layout as 26.0.24 as far as known, only the header version differs
+    V26_10_20,
 }
 
-impl std::fmt::Display for MinecraftVersion {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl MinecraftVersion {
+    /// Game release that introduced this format revision
+    pub fn release(self) -> [u32; 3] {
+        match self {
+            Self::V1_18_30 => [1, 18, 30],
+            Self::V1_19_60 => [1, 19, 60],
+            Self::V1_20_80 => [1, 20, 80],
+            Self::V1_21_20 => [1, 21, 20],
+            Self::V1_21_110 => [1, 21, 110],
+            Self::V26_0_24 => [26, 0, 24],
+            Self::V26_10_20 => [26, 10, 20],
+        }
+    }
+    /// Version numbers the header of this format revision can have, the first one is
+    /// written
+    pub fn header_versions(self) -> &'static [u64] {
         match self {
-            Self::V1_20_80 => write!(f, "1.20.80"),
-            Self::V1_19_60 => write!(f, "1.19.60"),
-            Self::V1_18_30 => write!(f, "1.18.30"),
-            Self::V1_21_20 => write!(f, "1.21.20"),
-            Self::V1_21_110 => write!(f, "1.21.110"),
-            Self::V26_0_24 => write!(f, "26.0.24"),
+            Self::V26_0_24 => &[23],
+            Self::V26_10_20 => &[25],
+            _ => &[22],
+        }
+    }
+    /// Map a game version such as "1.21.50.7" to the format revision it uses,
+    /// which is the newest revision released at or before it
+    pub fn from_game_version(version: &str) -> Option<Self> {
+        let mut release = [0u32; 3];
+        let mut parts = 0;
+        for (index, part) in version.trim().split('.').enumerate() {
+            let number: u32 = part.parse().ok()?;
+            if let Some(slot) = release.get_mut(index) {
+                *slot = number;
+            }
+            parts += 1;
+        }
+        if parts < 2 {
+            return None;
+        }
+        ALL_VERSIONS
+            .into_iter()
+            .rev()
+            .find(|v| v.release() <= release)
+    }
+}
+impl core::fmt::Display for MinecraftVersion {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let [major, minor, patch] = self.release();
+        write!(f, "{major}.{minor}.{patch}")
+    }
+}
+impl core::str::FromStr for MinecraftVersion {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ALL_VERSIONS
+            .into_iter()
+            .find(|v| v.to_string() == s.trim())
+            .ok_or_else(|| ParseEnumError::new("minecraft version", s))
+    }
+}
+
+/// Context for parsing a material or a part of one. Parsing with a bare
+/// [`MinecraftVersion`] is the same as strict options for that version
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ParseOptions {
+    pub version: MinecraftVersion,
+    /// Keep enum values this crate doesn't know as their `Unknown(raw)` variant, or
+    /// [`ShaderStage::Other`](pass::ShaderStage::Other), instead of failing. They are
+    /// written back unchanged, and only lenient parsing produces them
+    pub lenient: bool,
+}
+impl ParseOptions {
+    pub fn lenient(version: MinecraftVersion) -> Self {
+        Self {
+            version,
+            lenient: true,
+        }
+    }
+}
+impl From<MinecraftVersion> for ParseOptions {
+    fn from(version: MinecraftVersion) -> Self {
+        Self {
+            version,
+            lenient: false,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CompiledMaterialDefinition {
     pub version: u64,
     pub encryption_variant: EncryptionVariant,
@@ -59,13 +177,30 @@ pub struct CompiledMaterialDefinition {
     pub parent_name: Option<String>,
     pub sampler_definitions: IndexMap<String, SamplerDefinition>,
     pub property_fields: IndexMap<String, PropertyField>,
-    pub uniform_overrides: Option<IndexMap<String, String>>,
+    /// Uniforms fed from `Core/Builtins`, `None` before 1.21.110 and for `Core/Builtins`
+    pub uniform_overrides: Option<IndexMap<String, UniformOverride>>,
     pub passes: IndexMap<String, Pass>,
 }
-impl<'a> TryFromCtx<'a, MinecraftVersion> for CompiledMaterialDefinition {
+impl Hash for CompiledMaterialDefinition {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.version.hash(state);
+        self.encryption_variant.hash(state);
+        self.name.hash(state);
+        self.parent_name.hash(state);
+        hash_unordered(&self.sampler_definitions, state);
+        hash_unordered(&self.property_fields, state);
+        if let Some(overrides) = &self.uniform_overrides {
+            hash_unordered(overrides, state);
+        }
+        hash_unordered(&self.passes, state);
+    }
+}
+strict_ctx!(CompiledMaterialDefinition);
+impl<'a> TryFromCtx<'a, ParseOptions> for CompiledMaterialDefinition {
     type Error = MyError;
 
-    fn try_from_ctx(buffer: &'a [u8], ctx: MinecraftVersion) -> Result<(Self, usize), Self::Error> {
+    fn try_from_ctx(buffer: &'a [u8], options: ParseOptions) -> Result<(Self, usize), Self::Error> {
+        let ctx = options.version;
         let mut offset = 0;
         const MAGIC: u64 = 0xA11DA1A;
         if buffer.gread::<u64>(&mut offset)? != MAGIC {
@@ -83,12 +218,18 @@ impl<'a> TryFromCtx<'a, MinecraftVersion> for CompiledMaterialDefinition {
             .into());
         }
         let version: u64 = buffer.gread_with(&mut offset, LE)?;
-        if version == 23 && ctx != MinecraftVersion::V26_0_24 {
-            return Err(scroll::Error::BadInput {
-                size: offset,
-                msg: "Wrong material bin version",
-            }
-            .into());
+        // Headers before 26.0.24 weren't checked, some older files may not say 22
+        if !ctx.header_versions().contains(&version)
+            && (version > 22 || ctx >= MinecraftVersion::V26_0_24)
+        {
+            let known = ALL_VERSIONS
+                .iter()
+                .any(|v| v.header_versions().contains(&version));
+            return Err(MyError::custom(if known {
+                format!("Material header version {version} is not the one of {ctx}")
+            } else {
+                format!("Unsupported material header version {version}")
+            }));
         }
         let encryption_variant: EncryptionVariant = buffer.gread(&mut offset)?;
         if encryption_variant.is_encrypted() {
@@ -105,37 +246,37 @@ impl<'a> TryFromCtx<'a, MinecraftVersion> for CompiledMaterialDefinition {
             parent_name = Some(read_string(buffer, &mut offset)?);
         }
         let sampler_definition_count: u8 = buffer.gread_with(&mut offset, LE)?;
-        let mut sampler_definitions = IndexMap::with_capacity(sampler_definition_count.into());
+        let mut sampler_definitions =
+            IndexMap::with_capacity_and_hasher(sampler_definition_count.into(), Default::default());
         for _ in 0..sampler_definition_count {
             let name = read_string(buffer, &mut offset)?;
-            let sampler_definition: SamplerDefinition = buffer.gread_with(&mut offset, ctx)?;
+            let sampler_definition: SamplerDefinition = buffer.gread_with(&mut offset, options)?;
             sampler_definitions.insert(name, sampler_definition);
         }
         let property_field_count: u16 = buffer.gread_with(&mut offset, LE)?;
-        let mut property_fields = IndexMap::with_capacity(property_field_count.into());
+        let mut property_fields =
+            IndexMap::with_capacity_and_hasher(property_field_count.into(), Default::default());
         for _ in 0..property_field_count {
             let name = read_string(buffer, &mut offset)?;
-            let property_field: PropertyField = buffer.gread(&mut offset)?;
+            let property_field: PropertyField = buffer.gread_with(&mut offset, options)?;
             property_fields.insert(name, property_field);
         }
         let mut uniform_overrides = None;
-        if ctx >= MinecraftVersion::V1_21_110 {
-            if name != "Core/Builtins" {
-                let mut indexmap = IndexMap::new();
-                let builtin_count: u16 = buffer.gread_with(&mut offset, LE)?;
-                for _ in 0..builtin_count {
-                    let key = read_string(buffer, &mut offset)?;
-                    let value = read_string(buffer, &mut offset)?;
-                    indexmap.insert(key, value);
-                }
-                uniform_overrides = Some(indexmap);
+        if ctx >= MinecraftVersion::V1_21_110 && name != BUILTINS_NAME {
+            let mut indexmap = IndexMap::default();
+            let builtin_count: u16 = buffer.gread_with(&mut offset, LE)?;
+            for _ in 0..builtin_count {
+                let key = read_string(buffer, &mut offset)?;
+                let source = read_string(buffer, &mut offset)?;
+                indexmap.insert(key, UniformOverride { source });
             }
+            uniform_overrides = Some(indexmap);
         }
         let pass_count: u16 = buffer.gread_with(&mut offset, LE)?;
-        let mut passes = IndexMap::with_capacity(pass_count.into());
+        let mut passes = IndexMap::with_capacity_and_hasher(pass_count.into(), Default::default());
         for _ in 0..pass_count {
             let name = read_string(buffer, &mut offset)?;
-            let pass: Pass = buffer.gread_with(&mut offset, ctx)?;
+            let pass: Pass = buffer.gread_with(&mut offset, options)?;
             passes.insert(name, pass);
         }
         // Just so we parse the whole thing
@@ -168,61 +309,112 @@ impl<'a> TryFromCtx<'a, MinecraftVersion> for CompiledMaterialDefinition {
     }
 }
 impl CompiledMaterialDefinition {
+    /// Copy the pass named `from` to a new pass named `to`, returning the copy for editing.
+    /// `None` when there is no pass `from` or a pass `to` already exists
+    pub fn duplicate_pass(&mut self, from: &str, to: impl Into<String>) -> Option<&mut Pass> {
+        let to = to.into();
+        if self.passes.contains_key(&to) {
+            return None;
+        }
+        let pass = self.passes.get(from)?.clone();
+        let (index, _) = self.passes.insert_full(to, pass);
+        self.passes.get_index_mut(index).map(|(_, pass)| pass)
+    }
+    /// Number of bytes `write` produces for `version`
+    pub fn serialized_size(&self, version: MinecraftVersion) -> Result<usize, WriteError> {
+        serialized_size(|w| self.write_unchecked(w, version))
+    }
+    /// Check that the material can be written as `version`, reporting the first
+    /// collection or string too long for its length prefix
+    pub fn validate_for_write(&self, version: MinecraftVersion) -> Result<(), WriteError> {
+        self.serialized_size(version).map(|_| ())
+    }
+    /// Write the material as `version`. Everything is validated before the first byte
+    /// reaches `writer`, so only errors of `writer` itself can leave partial output
     pub fn write<W>(&self, writer: &mut W, version: MinecraftVersion) -> Result<(), WriteError>
     where
-        W: Write,
+        W: ByteWrite,
+    {
+        self.validate_for_write(version)?;
+        self.write_unchecked(writer, version)
+    }
+    /// Write the material as `version` into a buffer of its exact size. Sizing
+    /// validates it, so this goes over the material twice instead of three times
+    pub fn write_vec(&self, version: MinecraftVersion) -> Result<Vec<u8>, WriteError> {
+        let mut output = Vec::with_capacity(self.serialized_size(version)?);
+        self.write_unchecked(&mut output, version)?;
+        Ok(output)
+    }
+    fn write_unchecked<W>(
+        &self,
+        writer: &mut W,
+        version: MinecraftVersion,
+    ) -> Result<(), WriteError>
+    where
+        W: ByteWrite,
     {
         const MAGIC: u64 = 0xA11DA1A;
         writer.write_u64::<LittleEndian>(MAGIC)?;
         write_string("RenderDragon.CompiledMaterialDefinition", writer)?;
-        let ver: u64 = match version {
-            v if v <= MinecraftVersion::V1_21_110 => 22,
-            MinecraftVersion::V26_0_24 => 23,
-            // MinecraftVersion::V26_10_20 => 25,
-            _ => self.version,
-        };
-        writer.write_u64::<LittleEndian>(ver)?;
+        writer.write_u64::<LittleEndian>(version.header_versions()[0])?;
         self.encryption_variant.write(writer)?;
         write_string(&self.name, writer)?;
         optional_write(writer, self.parent_name.as_deref(), |o, v| {
             write_string(v, o)
         })?;
-        let len = self.sampler_definitions.len().try_into()?;
-        writer.write_u8(len)?;
+        writer.write_u8(checked_len(
+            self.sampler_definitions.len(),
+            "sampler_definitions",
+        )?)?;
         for (name, sampler_definition) in self.sampler_definitions.iter() {
             write_string(name, writer)?;
-            sampler_definition.write(writer, version)?;
+            sampler_definition
+                .write(writer, version)
+                .map_err(|e| e.at(format_args!("sampler_definitions[{name}]")))?;
         }
-        let len = self.property_fields.len().try_into()?;
+        let len = checked_len(self.property_fields.len(), "property_fields")?;
         writer.write_u16::<LittleEndian>(len)?;
         for (name, property_field) in self.property_fields.iter() {
             write_string(name, writer)?;
-            property_field.write(writer)?;
+            property_field
+                .write(writer)
+                .map_err(|e| e.at(format_args!("property_fields[{name}]")))?;
         }
-        if version >= MinecraftVersion::V1_21_110 && self.name != "Core/Builtins" {
+        if version >= MinecraftVersion::V1_21_110 && self.name != BUILTINS_NAME {
             match &self.uniform_overrides {
                 Some(overrides) => {
-                    writer.write_u16::<LittleEndian>(overrides.len().try_into()?)?;
+                    let len = checked_len(overrides.len(), "uniform_overrides")?;
+                    writer.write_u16::<LittleEndian>(len)?;
                     for (key, value) in overrides {
                         write_string(key, writer)?;
-                        write_string(value, writer)?;
+                        write_string(&value.source, writer)?;
                     }
                 }
                 None => writer.write_u16::<LittleEndian>(0)?,
             }
+        } else if self.name != BUILTINS_NAME
+            && self
+                .uniform_overrides
+                .as_ref()
+                .is_some_and(|o| !o.is_empty())
+        {
+            return Err(WriteError::Compat(format!(
+                "Uniform overrides can't be stored in {version}, inline them first"
+            )));
         }
-        let len = self.passes.len().try_into()?;
+        let len = checked_len(self.passes.len(), "passes")?;
         writer.write_u16::<LittleEndian>(len)?;
         for (name, pass) in self.passes.iter() {
             write_string(name, writer)?;
-            pass.write(writer, version)?;
+            pass.write(writer, version)
+                .map_err(|e| e.at(format_args!("passes[{name}]")))?;
         }
         writer.write_u64::<LittleEndian>(MAGIC)?;
         Ok(())
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
 pub enum EncryptionVariant {
     None,
     SimplePassphrase,
@@ -249,9 +441,9 @@ impl<'a> TryFromCtx<'a> for EncryptionVariant {
     }
 }
 impl EncryptionVariant {
-    fn write<W>(&self, output: &mut W) -> Result<(), std::io::Error>
+    fn write<W>(&self, output: &mut W) -> Result<(), WriteError>
     where
-        W: Write,
+        W: ByteWrite,
     {
         let int = match self {
             Self::None => 0x4E4F4E45_u32,
@@ -268,35 +460,79 @@ impl EncryptionVariant {
 
 #[derive(Debug)]
 pub enum WriteError {
-    IntConvert(std::num::TryFromIntError),
+    IntConvert(core::num::TryFromIntError),
+    #[cfg(feature = "std")]
     IoError(std::io::Error),
     Compat(String),
+    /// A collection or string at `path` has more entries than its length prefix can hold
+    Limit {
+        path: String,
+        len: usize,
+        max: usize,
+    },
 }
+impl WriteError {
+    /// Prefix the path of a limit error with the entry it happened in
+    pub(crate) fn at(self, segment: impl Display) -> Self {
+        match self {
+            Self::Limit { path, len, max } => Self::Limit {
+                path: format!("{segment}.{path}"),
+                len,
+                max,
+            },
+            other => other,
+        }
+    }
+}
+#[cfg(feature = "std")]
 impl From<std::io::Error> for WriteError {
     fn from(value: std::io::Error) -> Self {
         Self::IoError(value)
     }
 }
-impl From<std::num::TryFromIntError> for WriteError {
-    fn from(io_error: std::num::TryFromIntError) -> Self {
+impl From<core::num::TryFromIntError> for WriteError {
+    fn from(io_error: core::num::TryFromIntError) -> Self {
         Self::IntConvert(io_error)
     }
 }
-impl std::error::Error for WriteError {}
-impl std::fmt::Display for WriteError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::error::Error for WriteError {}
+impl core::fmt::Display for WriteError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::IntConvert(err) => write!(f, "Int conversion failed: {err}"),
+            #[cfg(feature = "std")]
             Self::IoError(err) => write!(f, "Io error: {err}"),
             Self::Compat(info) => write!(f, "Compat error: {info}"),
+            Self::Limit { path, len, max } => {
+                write!(f, "{path} has length {len}, more than the limit of {max}")
+            }
+        }
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseEnumError {
+    pub kind: &'static str,
+    pub value: String,
+}
+impl ParseEnumError {
+    pub(crate) fn new(kind: &'static str, value: &str) -> Self {
+        Self {
+            kind,
+            value: value.to_string(),
         }
     }
 }
+impl core::error::Error for ParseEnumError {}
+impl Display for ParseEnumError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Unknown {}: {}", self.kind, self.value)
+    }
+}
 #[macro_export]
 macro_rules! option_read {
     ($buf:expr, $offset:expr, $func:expr) => {
         // let should_read = crate::common::read_bool($offset, $buf);
-        if crate::common::read_bool($offset, $buf)? {
+        if $crate::common::read_bool($offset, $buf)? {
             Some($func)
         } else {
             None
@@ -320,24 +556,33 @@ impl From<scroll::Error> for MyError {
 }
 
 impl MyError {
+    pub(crate) fn custom(msg: impl Into<String>) -> Self {
+        Self {
+            #[cfg(feature = "backtracing")]
+            backtrace: Box::new(Backtrace::capture()),
+            thingy: MyErrorThingy::Custom(msg.into()),
+        }
+    }
     #[cfg(feature = "backtracing")]
     pub fn get_backtracey(&self) -> &Box<Backtrace> {
         &self.backtrace
     }
 }
 impl Display for MyError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{}", self.thingy)
     }
 }
 #[derive(Debug)]
 pub enum MyErrorThingy {
     Scroll(scroll::Error),
+    Custom(String),
 }
 impl Display for MyErrorThingy {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::Scroll(e) => write!(f, "{e}"),
+            Self::Custom(msg) => write!(f, "{msg}"),
         }
     }
 }
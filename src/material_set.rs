@@ -0,0 +1,108 @@
+use alloc::{string::String, vec::Vec};
+use core::fmt::Display;
+
+use crate::{
+    uniform_override::LostOverride, CompiledMaterialDefinition, IndexMap, MinecraftVersion,
+    BUILTINS_NAME,
+};
+
+/// Materials of one pack or game version, so checks that span materials can look up
+/// `Core/Builtins`
+#[derive(Debug, Clone, Default)]
+pub struct MaterialSet {
+    pub materials: IndexMap<String, CompiledMaterialDefinition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OverrideError {
+    /// A material has overrides but the set has no `Core/Builtins`
+    MissingBuiltins { material: String },
+    /// An override's source isn't a property field of `Core/Builtins`
+    UnknownSource {
+        material: String,
+        uniform: String,
+        source: String,
+    },
+}
+impl core::error::Error for OverrideError {}
+impl Display for OverrideError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::MissingBuiltins { material } => {
+                write!(f, "{material} has uniform overrides but there is no {BUILTINS_NAME}")
+            }
+            Self::UnknownSource {
+                material,
+                uniform,
+                source,
+            } => write!(
+                f,
+                "{material}: uniform {uniform} is overridden by {source}, which {BUILTINS_NAME} doesn't have"
+            ),
+        }
+    }
+}
+
+impl MaterialSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add a material, replacing and returning any with the same name
+    pub fn insert(
+        &mut self,
+        material: CompiledMaterialDefinition,
+    ) -> Option<CompiledMaterialDefinition> {
+        self.materials.insert(material.name.clone(), material)
+    }
+    pub fn get(&self, name: &str) -> Option<&CompiledMaterialDefinition> {
+        self.materials.get(name)
+    }
+    pub fn builtins(&self) -> Option<&CompiledMaterialDefinition> {
+        self.get(BUILTINS_NAME)
+    }
+    /// Check that every uniform override points at a property field of `Core/Builtins`
+    pub fn validate_uniform_overrides(&self) -> Result<(), Vec<OverrideError>> {
+        let builtins = self.builtins();
+        let mut errors = Vec::new();
+        for material in self.materials.values() {
+            let Some(overrides) = &material.uniform_overrides else {
+                continue;
+            };
+            let Some(builtins) = builtins else {
+                if !overrides.is_empty() {
+                    errors.push(OverrideError::MissingBuiltins {
+                        material: material.name.clone(),
+                    });
+                }
+                continue;
+            };
+            for (uniform, uniform_override) in overrides {
+                if !builtins
+                    .property_fields
+                    .contains_key(&uniform_override.source)
+                {
+                    errors.push(OverrideError::UnknownSource {
+                        material: material.name.clone(),
+                        uniform: uniform.clone(),
+                        source: uniform_override.source.clone(),
+                    });
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+    /// Prepare every material for writing as `version`, inlining uniform overrides
+    /// from `Core/Builtins` when the version can't store them.
+    /// See [`CompiledMaterialDefinition::inline_uniform_overrides`]
+    pub fn inline_uniform_overrides(&mut self, version: MinecraftVersion) -> Vec<LostOverride> {
+        let builtins = self.builtins().cloned();
+        self.materials
+            .values_mut()
+            .flat_map(|material| material.inline_uniform_overrides(version, builtins.as_ref()))
+            .collect()
+    }
+}
@@ -0,0 +1,318 @@
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Display;
+
+use crate::{
+    diff::{by_name, shader_code_key, variant_keys},
+    pass::{Pass, PlatformShaderStage, ShaderCode, Variant},
+    CompiledMaterialDefinition, IndexMap,
+};
+
+/// Merge a single field, keeping `theirs` on conflict
+macro_rules! merge_field {
+    ($conflicts:expr, $path:expr, $base:expr, $ours:expr, $theirs:expr, $field:ident) => {
+        pick(
+            $base.map(|b| &b.$field),
+            Some(&$ours.$field),
+            Some(&$theirs.$field),
+            || {
+                $conflicts.push(MergeConflict::Value {
+                    path: format!("{}.{}", $path, stringify!($field)),
+                })
+            },
+        )
+        .unwrap_or(&$theirs.$field)
+        .clone()
+    };
+}
+
+/// Outcome of a three-way [`merge`]
+#[derive(Debug)]
+pub struct MergeResult {
+    pub material: CompiledMaterialDefinition,
+    pub conflicts: Vec<MergeConflict>,
+}
+impl MergeResult {
+    pub fn has_conflicts(&self) -> bool {
+        !self.conflicts.is_empty()
+    }
+}
+/// A change made on both sides that could not be merged.
+/// The merged material keeps the value from `theirs` when present, else from `ours`
+#[derive(Debug)]
+pub enum MergeConflict {
+    Value {
+        path: String,
+    },
+    ShaderCode {
+        path: String,
+        stage: PlatformShaderStage,
+        ours: Option<Box<ShaderCode>>,
+        theirs: Option<Box<ShaderCode>>,
+    },
+}
+impl Display for MergeConflict {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Value { path } => write!(f, "{path}: changed on both sides"),
+            Self::ShaderCode { path, stage, .. } => write!(
+                f,
+                "{path}.shader_codes[{}]: shader code changed on both sides",
+                shader_code_key(stage)
+            ),
+        }
+    }
+}
+
+/// Merge the changes between `base` and `ours` onto `theirs`.
+///
+/// Samplers, properties, uniform overrides and passes are matched by name,
+/// variants by flag set and shader codes by platform and stage, so our edits
+/// survive upstream reordering. Shader codes we replaced are kept as long as
+/// upstream didn't touch the same one.
+pub fn merge(
+    base: &CompiledMaterialDefinition,
+    ours: &CompiledMaterialDefinition,
+    theirs: &CompiledMaterialDefinition,
+) -> MergeResult {
+    let mut conflicts = Vec::new();
+    let path = theirs.name.clone();
+    let version = merge_field!(conflicts, path, Some(base), ours, theirs, version);
+    let encryption_variant = merge_field!(
+        conflicts,
+        path,
+        Some(base),
+        ours,
+        theirs,
+        encryption_variant
+    );
+    let name = merge_field!(conflicts, path, Some(base), ours, theirs, name);
+    let parent_name = merge_field!(conflicts, path, Some(base), ours, theirs, parent_name);
+    let sampler_definitions = merge_leaf_maps(
+        &format!("{path}.sampler_definitions"),
+        &base.sampler_definitions,
+        &ours.sampler_definitions,
+        &theirs.sampler_definitions,
+        &mut conflicts,
+    );
+    let property_fields = merge_leaf_maps(
+        &format!("{path}.property_fields"),
+        &base.property_fields,
+        &ours.property_fields,
+        &theirs.property_fields,
+        &mut conflicts,
+    );
+    let uniform_overrides = match (&ours.uniform_overrides, &theirs.uniform_overrides) {
+        (None, None) => None,
+        _ => Some(merge_leaf_maps(
+            &format!("{path}.uniform_overrides"),
+            base.uniform_overrides
+                .as_ref()
+                .unwrap_or(&IndexMap::default()),
+            ours.uniform_overrides
+                .as_ref()
+                .unwrap_or(&IndexMap::default()),
+            theirs
+                .uniform_overrides
+                .as_ref()
+                .unwrap_or(&IndexMap::default()),
+            &mut conflicts,
+        )),
+    };
+    let passes = merge_maps(
+        &by_name(&base.passes),
+        &by_name(&ours.passes),
+        &by_name(&theirs.passes),
+        |key, b, o, t| {
+            let path = format!("{path}.passes[{key}]");
+            match (o, t) {
+                (Some(o), Some(t)) => Some(merge_pass(&path, b, o, t, &mut conflicts)),
+                _ => {
+                    conflicts.push(MergeConflict::Value { path });
+                    t.or(o).cloned()
+                }
+            }
+        },
+    );
+    MergeResult {
+        material: CompiledMaterialDefinition {
+            version,
+            encryption_variant,
+            name,
+            parent_name,
+            sampler_definitions,
+            property_fields,
+            uniform_overrides,
+            passes,
+        },
+        conflicts,
+    }
+}
+fn merge_pass(
+    path: &str,
+    base: Option<&Pass>,
+    ours: &Pass,
+    theirs: &Pass,
+    conflicts: &mut Vec<MergeConflict>,
+) -> Pass {
+    let bitset = merge_field!(conflicts, path, base, ours, theirs, bitset);
+    let fallback = merge_field!(conflicts, path, base, ours, theirs, fallback);
+    let default_blendmode = merge_field!(conflicts, path, base, ours, theirs, default_blendmode);
+    let framebuffer_binding =
+        merge_field!(conflicts, path, base, ours, theirs, framebuffer_binding);
+    let default_flag_values = merge_leaf_maps(
+        &format!("{path}.default_flag_values"),
+        base.map(|b| &b.default_flag_values)
+            .unwrap_or(&IndexMap::default()),
+        &ours.default_flag_values,
+        &theirs.default_flag_values,
+        conflicts,
+    );
+    fn variants(pass: Option<&Pass>) -> IndexMap<String, &Variant> {
+        pass.map(|pass| variant_keys(&pass.variants))
+            .unwrap_or_default()
+    }
+    let variants = merge_maps(
+        &variants(base),
+        &variants(Some(ours)),
+        &variants(Some(theirs)),
+        |key, b, o, t| {
+            let path = format!("{path}.variants[{key}]");
+            match (o, t) {
+                (Some(o), Some(t)) => Some(merge_variant(&path, b, o, t, conflicts)),
+                _ => {
+                    conflicts.push(MergeConflict::Value { path });
+                    t.or(o).cloned()
+                }
+            }
+        },
+    );
+    Pass {
+        bitset,
+        fallback,
+        default_blendmode,
+        default_flag_values,
+        framebuffer_binding,
+        variants: variants.into_values().collect(),
+    }
+}
+fn merge_variant(
+    path: &str,
+    base: Option<&Variant>,
+    ours: &Variant,
+    theirs: &Variant,
+    conflicts: &mut Vec<MergeConflict>,
+) -> Variant {
+    let is_supported = merge_field!(conflicts, path, base, ours, theirs, is_supported);
+    fn shader_codes(variant: Option<&Variant>) -> IndexMap<String, &ShaderCode> {
+        variant
+            .map(|variant| {
+                variant
+                    .shader_codes
+                    .iter()
+                    .map(|(stage, code)| (shader_code_key(stage), code))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    let stages: IndexMap<String, &PlatformShaderStage> = theirs
+        .shader_codes
+        .keys()
+        .chain(ours.shader_codes.keys())
+        .map(|stage| (shader_code_key(stage), stage))
+        .collect();
+    let shader_codes = merge_maps(
+        &shader_codes(base),
+        &shader_codes(Some(ours)),
+        &shader_codes(Some(theirs)),
+        |key, _, o, t| {
+            conflicts.push(MergeConflict::ShaderCode {
+                path: path.to_string(),
+                stage: stages[key].clone(),
+                ours: o.cloned().map(Box::new),
+                theirs: t.cloned().map(Box::new),
+            });
+            t.or(o).cloned()
+        },
+    );
+    Variant {
+        is_supported,
+        // Variants are matched by flag set, so every side agrees on the flags
+        flags: theirs.flags.clone(),
+        shader_codes: shader_codes
+            .into_iter()
+            .map(|(key, code)| (stages[&key].clone(), code))
+            .collect(),
+    }
+}
+/// Pick the side that changed relative to `base`, calling `on_conflict`
+/// and returning `None` if both changed differently
+fn pick<'a, T: PartialEq + ?Sized>(
+    base: Option<&'a T>,
+    ours: Option<&'a T>,
+    theirs: Option<&'a T>,
+    on_conflict: impl FnOnce(),
+) -> Option<&'a T> {
+    if ours == theirs || ours == base {
+        theirs
+    } else if theirs == base {
+        ours
+    } else {
+        on_conflict();
+        None
+    }
+}
+fn merge_leaf_maps<V: Clone + PartialEq>(
+    path: &str,
+    base: &IndexMap<String, V>,
+    ours: &IndexMap<String, V>,
+    theirs: &IndexMap<String, V>,
+    conflicts: &mut Vec<MergeConflict>,
+) -> IndexMap<String, V> {
+    merge_maps(
+        &by_name(base),
+        &by_name(ours),
+        &by_name(theirs),
+        |key, _, o, t| {
+            conflicts.push(MergeConflict::Value {
+                path: format!("{path}[{key}]"),
+            });
+            t.or(o).cloned()
+        },
+    )
+}
+/// Three-way merge of keyed entries, keeping `theirs` order with our
+/// additions appended. `on_conflict` resolves entries changed on both sides
+fn merge_maps<V, F>(
+    base: &IndexMap<String, &V>,
+    ours: &IndexMap<String, &V>,
+    theirs: &IndexMap<String, &V>,
+    mut on_conflict: F,
+) -> IndexMap<String, V>
+where
+    V: Clone + PartialEq,
+    F: FnMut(&str, Option<&V>, Option<&V>, Option<&V>) -> Option<V>,
+{
+    let keys = theirs
+        .keys()
+        .chain(ours.keys().filter(|key| !theirs.contains_key(*key)));
+    let mut merged = IndexMap::default();
+    for key in keys {
+        let (b, o, t) = (base.get(key), ours.get(key), theirs.get(key));
+        let mut conflicted = false;
+        let picked = pick(b, o, t, || conflicted = true);
+        let value = if conflicted {
+            on_conflict(key, b.copied(), o.copied(), t.copied())
+        } else {
+            picked.map(|v| (*v).clone())
+        };
+        if let Some(value) = value {
+            merged.insert(key.clone(), value);
+        }
+    }
+    merged
+}
@@ -1,15 +1,22 @@
-use std::{fmt::Display, io::Write};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::hash::{Hash, Hasher};
 
-use byteorder::{LittleEndian, WriteBytesExt};
-use indexmap::IndexMap;
+use byteorder::LittleEndian;
 use scroll::{ctx::TryFromCtx, Pread, LE};
 
-use crate::MyError;
 use crate::{
-    common::{optional_write, read_bool, read_string, write_string},
-    option_read, MinecraftVersion, WriteError,
+    common::{
+        checked_len, enum_names, hash_unordered, optional_write, raw_enum, read_bool, read_string,
+        serialized_size, strict_ctx, write_string,
+    },
+    option_read, MinecraftVersion, ParseOptions, WriteError,
 };
-#[derive(Debug)]
+use crate::{ByteWrite, IndexMap, MyError};
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub struct Pass {
     pub bitset: String,
     pub fallback: String,
@@ -18,34 +25,63 @@ pub struct Pass {
     pub framebuffer_binding: Option<u32>,
     pub variants: Vec<Variant>,
 }
-impl<'a> TryFromCtx<'a, MinecraftVersion> for Pass {
+impl Hash for Pass {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bitset.hash(state);
+        self.fallback.hash(state);
+        self.default_blendmode.hash(state);
+        hash_unordered(&self.default_flag_values, state);
+        self.framebuffer_binding.hash(state);
+        self.variants.hash(state);
+    }
+}
+strict_ctx!(Pass);
+impl<'a> TryFromCtx<'a, ParseOptions> for Pass {
     type Error = MyError;
-    fn try_from_ctx(buffer: &'a [u8], ctx: MinecraftVersion) -> Result<(Self, usize), Self::Error> {
+    fn try_from_ctx(buffer: &'a [u8], options: ParseOptions) -> Result<(Self, usize), Self::Error> {
+        let ctx = options.version;
         let mut offset = 0;
         let bitset = if ctx == MinecraftVersion::V1_18_30 {
-            let has_bitset = buffer.gread_with::<u8>(&mut offset, LE)? == 15;
-            // rewind
-            offset -= 4;
-            if has_bitset {
-                read_string(buffer, &mut offset)?
-            } else {
-                // skip reading byte we have no use for
+            // A 1.18.30 pass starts with either
+            // - the bitset: u32 length of one of `bitset_lengths`, then as many '0'/'1'
+            // - a single 0 byte when it has none, then the fallback string
+            // No flag tells them apart, but a length of 14 or 15 can't start with the
+            // placeholder, so any other first byte is an error. 1.18.30 has no empty
+            // bitset string: a zero length is the placeholder and an empty fallback
+            let len: u32 = buffer.pread_with(offset, LE)?;
+            if bitset_lengths(ctx).any(|bitset_len| bitset_len == len as usize) {
+                let bitset = read_string(buffer, &mut offset)?;
+                if bitset.bytes().any(|bit| bit != b'0' && bit != b'1') {
+                    return Err(MyError::custom(format!(
+                        "Invalid 1.18.30 pass bitset: {bitset:?}"
+                    )));
+                }
+                bitset
+            } else if buffer.pread::<u8>(offset)? == 0 {
                 offset += 1;
-                "".to_string()
+                String::new()
+            } else {
+                return Err(MyError::custom(format!(
+                    "Invalid 1.18.30 pass bitset length: {len}"
+                )));
             }
         } else {
             read_string(buffer, &mut offset)?
         };
         let fallback = read_string(buffer, &mut offset)?;
-        let default_blendmode: Option<BlendMode> =
-            option_read!(&mut offset, buffer, buffer.gread(&mut offset)?);
+        let default_blendmode: Option<BlendMode> = option_read!(
+            &mut offset,
+            buffer,
+            buffer.gread_with(&mut offset, options)?
+        );
         // let has_blendmode = read_bool(buffer, &mut offset)?;
         // if has_blendmode {
         //     default_blendmode = Some(buffer.gread_with(&mut offset, ())?);
         // }
 
         let flag_dvalue_count: u16 = buffer.gread_with(&mut offset, LE)?;
-        let mut default_flag_values = IndexMap::with_capacity(flag_dvalue_count.into());
+        let mut default_flag_values =
+            IndexMap::with_capacity_and_hasher(flag_dvalue_count.into(), Default::default());
         for _ in 0..flag_dvalue_count {
             let key = read_string(buffer, &mut offset)?;
             let value = read_string(buffer, &mut offset)?;
@@ -57,7 +93,7 @@ impl<'a> TryFromCtx<'a, MinecraftVersion> for Pass {
         }
         let variant_count: u16 = buffer.gread_with(&mut offset, LE)?;
         let variants: Result<Vec<Variant>, MyError> = (0..variant_count)
-            .map(|_| buffer.gread_with(&mut offset, ctx))
+            .map(|_| buffer.gread_with(&mut offset, options))
             .collect();
         Ok((
             Self {
@@ -73,22 +109,77 @@ impl<'a> TryFromCtx<'a, MinecraftVersion> for Pass {
     }
 }
 impl Pass {
+    /// Number of bytes `write` produces for `version`
+    pub fn serialized_size(&self, version: MinecraftVersion) -> Result<usize, WriteError> {
+        serialized_size(|w| self.write(w, version))
+    }
+    /// Bitset of the platforms this pass has shader code for in `version`, in the form
+    /// of `std::bitset::to_string`: one character per ID of
+    /// [`ShaderCodePlatform::platforms`], the highest ID first
+    pub fn platform_bitset(&self, version: MinecraftVersion) -> String {
+        ShaderCodePlatform::platforms(version)
+            .iter()
+            .rev()
+            .map(|platform| {
+                let used = self
+                    .variants
+                    .iter()
+                    .flat_map(|variant| variant.shader_codes.keys())
+                    .any(|stage| stage.platform == *platform);
+                if used {
+                    '1'
+                } else {
+                    '0'
+                }
+            })
+            .collect()
+    }
+    /// [`Self::bitset`] renumbered for the platform IDs of `version`. The numbering it
+    /// was stored with is told by its length, bitsets `version` can store as they are
+    /// and bitsets matching no numbering are kept as is
+    pub fn bitset_for(&self, version: MinecraftVersion) -> String {
+        if bitset_lengths(version).any(|len| len == self.bitset.len()) {
+            return self.bitset.clone();
+        }
+        let source = PLATFORM_IDS
+            .iter()
+            .map(|(_, platforms)| *platforms)
+            .find(|platforms| platforms.len() == self.bitset.len());
+        let Some(source) = source else {
+            return self.bitset.clone();
+        };
+        let target = ShaderCodePlatform::platforms(version);
+        let set: Vec<ShaderCodePlatform> = source
+            .iter()
+            .rev()
+            .zip(self.bitset.chars())
+            .filter(|(_, bit)| *bit == '1')
+            .map(|(platform, _)| *platform)
+            .collect();
+        target
+            .iter()
+            .rev()
+            .map(|platform| if set.contains(platform) { '1' } else { '0' })
+            .collect()
+    }
     pub fn write<W>(&self, writer: &mut W, version: MinecraftVersion) -> Result<(), WriteError>
     where
-        W: Write,
+        W: ByteWrite,
     {
-        if self.bitset.is_empty() {
-            return Err(WriteError::Compat(
-                "Bitset string is empty, Try fixing it in the main struct".to_string(),
-            ));
+        // Only 1.18.30 can leave out the bitset, newer revisions get one derived
+        // from the shader codes
+        if !self.bitset.is_empty() {
+            write_string(&self.bitset_for(version), writer)?;
         } else if version == MinecraftVersion::V1_18_30 {
+            writer.write_u8(0)?;
+        } else {
+            write_string(&self.platform_bitset(version), writer)?;
         }
-        write_string(&self.bitset, writer)?;
         write_string(&self.fallback, writer)?;
         optional_write(writer, self.default_blendmode.as_ref(), |o, v| {
-            o.write_u16::<LittleEndian>(v.as_u16())
+            o.write_u16::<LittleEndian>(v.to_u16())
         })?;
-        let len = self.default_flag_values.len().try_into()?;
+        let len = checked_len(self.default_flag_values.len(), "default_flag_values")?;
         writer.write_u16::<LittleEndian>(len)?;
         for (key, value) in self.default_flag_values.iter() {
             write_string(key, writer)?;
@@ -101,41 +192,49 @@ impl Pass {
                 writer.write_u32::<LittleEndian>(0)?;
             }
         }
-        let len = self.variants.len().try_into()?;
+        let len = checked_len(self.variants.len(), "variants")?;
         writer.write_u16::<LittleEndian>(len)?;
-        for variant in self.variants.iter() {
-            variant.write(writer, version)?;
+        for (index, variant) in self.variants.iter().enumerate() {
+            variant
+                .write(writer, version)
+                .map_err(|e| e.at(format_args!("variants[{index}]")))?;
         }
         Ok(())
     }
 }
-#[derive(Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub struct Variant {
     pub is_supported: bool,
     pub flags: IndexMap<String, String>,
     pub shader_codes: IndexMap<PlatformShaderStage, ShaderCode>,
 }
-impl<'a> TryFromCtx<'a, MinecraftVersion> for Variant {
+impl Hash for Variant {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.is_supported.hash(state);
+        hash_unordered(&self.flags, state);
+        hash_unordered(&self.shader_codes, state);
+    }
+}
+strict_ctx!(Variant);
+impl<'a> TryFromCtx<'a, ParseOptions> for Variant {
     type Error = MyError;
 
-    fn try_from_ctx(
-        buffer: &'a [u8],
-        version: MinecraftVersion,
-    ) -> Result<(Self, usize), Self::Error> {
+    fn try_from_ctx(buffer: &'a [u8], options: ParseOptions) -> Result<(Self, usize), Self::Error> {
         let mut offset = 0;
         let is_supported = read_bool(buffer, &mut offset)?;
         let flag_count: u16 = buffer.gread_with(&mut offset, LE)?;
         let shader_code_count: u16 = buffer.gread_with(&mut offset, LE)?;
-        let mut flags = IndexMap::with_capacity(flag_count.into());
+        let mut flags = IndexMap::with_capacity_and_hasher(flag_count.into(), Default::default());
         for _ in 0..flag_count {
             let key = read_string(buffer, &mut offset)?;
             let value = read_string(buffer, &mut offset)?;
             flags.insert(key, value);
         }
-        let mut shader_codes = IndexMap::with_capacity(shader_code_count.into());
+        let mut shader_codes =
+            IndexMap::with_capacity_and_hasher(shader_code_count.into(), Default::default());
         for _ in 0..shader_code_count {
-            let stage: PlatformShaderStage = buffer.gread_with(&mut offset, version)?;
-            let shader_code: ShaderCode = buffer.gread(&mut offset)?;
+            let stage: PlatformShaderStage = buffer.gread_with(&mut offset, options)?;
+            let shader_code: ShaderCode = buffer.gread_with(&mut offset, options)?;
             shader_codes.insert(stage, shader_code);
         }
         Ok((
@@ -149,14 +248,18 @@ impl<'a> TryFromCtx<'a, MinecraftVersion> for Variant {
     }
 }
 impl Variant {
+    /// Number of bytes `write` produces for `version`
+    pub fn serialized_size(&self, version: MinecraftVersion) -> Result<usize, WriteError> {
+        serialized_size(|w| self.write(w, version))
+    }
     pub fn write<W>(&self, writer: &mut W, version: MinecraftVersion) -> Result<(), WriteError>
     where
-        W: Write,
+        W: ByteWrite,
     {
         writer.write_u8(self.is_supported.into())?;
-        let len = self.flags.len().try_into()?;
+        let len = checked_len(self.flags.len(), "flags")?;
         writer.write_u16::<LittleEndian>(len)?;
-        let len = self.shader_codes.len().try_into()?;
+        let len = checked_len(self.shader_codes.len(), "shader_codes")?;
         writer.write_u16::<LittleEndian>(len)?;
         for flag in self.flags.iter() {
             write_string(flag.0, writer)?;
@@ -164,14 +267,18 @@ impl Variant {
         }
         for (platform_stage, code) in self.shader_codes.iter() {
             platform_stage.write(writer, version)?;
-            code.write(writer)?;
+            code.write(writer).map_err(|e| {
+                e.at(format_args!(
+                    "shader_codes[{}]",
+                    crate::diff::shader_code_key(platform_stage)
+                ))
+            })?;
         }
 
         Ok(())
     }
 }
-#[derive(PartialEq, Eq, Debug)]
-#[repr(u16)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
 pub enum BlendMode {
     None,
     Replace,
@@ -185,50 +292,37 @@ pub enum BlendMode {
     MultiplyBoth,
     InverseSrcAlpha,
     SrcAlpha,
-}
-impl<'a> TryFromCtx<'a> for BlendMode {
-    type Error = MyError;
-    #[inline(never)]
-    fn try_from_ctx(buffer: &'a [u8], _: ()) -> Result<(Self, usize), Self::Error> {
-        let int: u16 = buffer.pread_with(0, LE)?;
-        let enum_type = match int {
-            0 => Self::None,
-            1 => Self::Replace,
-            2 => Self::AlphaBlend,
-            3 => Self::ColorBlendAlphaAdd,
-            4 => Self::PreMultiplied,
-            5 => Self::InvertColor,
-            6 => Self::Additive,
-            7 => Self::AdditiveAlpha,
-            8 => Self::Multiply,
-            9 => Self::MultiplyBoth,
-            10 => Self::InverseSrcAlpha,
-            11 => Self::SrcAlpha,
-            _ => return Err(scroll::Error::Custom(format!("Invalid blend_mode: {int}")).into()),
-        };
-        Ok((enum_type, 2))
-    }
-}
-impl BlendMode {
-    fn as_u16(&self) -> u16 {
-        match self {
-            Self::None => 0,
-            Self::Replace => 1,
-            Self::AlphaBlend => 2,
-            Self::ColorBlendAlphaAdd => 3,
-            Self::PreMultiplied => 4,
-            Self::InvertColor => 5,
-            Self::Additive => 6,
-            Self::AdditiveAlpha => 7,
-            Self::Multiply => 8,
-            Self::MultiplyBoth => 9,
-            Self::InverseSrcAlpha => 10,
-            Self::SrcAlpha => 11,
-        }
-    }
-}
+    Unknown(u16),
+}
+raw_enum!(BlendMode, u16, to_u16, "blend mode", {
+    None = 0,
+    Replace = 1,
+    AlphaBlend = 2,
+    ColorBlendAlphaAdd = 3,
+    PreMultiplied = 4,
+    InvertColor = 5,
+    Additive = 6,
+    AdditiveAlpha = 7,
+    Multiply = 8,
+    MultiplyBoth = 9,
+    InverseSrcAlpha = 10,
+    SrcAlpha = 11,
+});
+enum_names!(BlendMode, "blend mode", {
+    None => "None",
+    Replace => "Replace",
+    AlphaBlend => "AlphaBlend",
+    ColorBlendAlphaAdd => "ColorBlendAlphaAdd",
+    PreMultiplied => "PreMultiplied",
+    InvertColor => "InvertColor",
+    Additive => "Additive",
+    AdditiveAlpha => "AdditiveAlpha",
+    Multiply => "Multiply",
+    MultiplyBoth => "MultiplyBoth",
+    InverseSrcAlpha => "InverseSrcAlpha",
+    SrcAlpha => "SrcAlpha",
+}, Unknown);
 #[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
-#[repr(u8)]
 pub enum ShaderCodePlatform {
     Direct3DSm40, //Windows
     Direct3DSm50, //Windows
@@ -245,122 +339,160 @@ pub enum ShaderCodePlatform {
     Vulkan,       //Nintendo Switch
     Nvn,          //?
     Pssl,         //?
-}
-// const PLATFORMS: [ShaderCodePlatform; 16] = [
-//     ShaderCodePlatform::Direct3DSm40,
-//     ShaderCodePlatform::Direct3DSm50,
-//     ShaderCodePlatform::Direct3DSm60,
-//     ShaderCodePlatform::Direct3DSm65,
-//     ShaderCodePlatform::Direct3DXB1,
-//     ShaderCodePlatform::Direct3DXBX,
-//     ShaderCodePlatform::Glsl120,
-//     ShaderCodePlatform::Glsl120,
-//     ShaderCodePlatform::Glsl430,
-//     ShaderCodePlatform::Essl100,
-//     ShaderCodePlatform::Essl300,
-//     ShaderCodePlatform::Essl310,
-//     ShaderCodePlatform::Metal,
-//     ShaderCodePlatform::Vulkan,
-//     ShaderCodePlatform::Nvn,
-//     ShaderCodePlatform::Pssl,
-// ];
-// impl<'a> TryFromCtx<'a, MinecraftVersion> for ShaderCodePlatform {
-//     type Error = MyError;
-//     #[inline(never)]
-//     fn try_from_ctx(
-//         buffer: &'a [u8],
-//         version: MinecraftVersion,
-//     ) -> Result<(Self, usize), Self::Error> {
-//         let int: u8 = buffer.pread_with(0, LE)?;
-//         let mut version_list = PLATFORMS
-//             .into_iter()
-//             .filter(|v| version < MinecraftVersion::V1_21_20 && *v != ShaderCodePlatform::Essl100);
-//         // .filter(|p| version < MinecraftVersion::V26_0_24 && *p != ShaderCodePlatform::Essl300);
-
-//         let enum_type = match version_list.nth(int.into()) {
-//             Some(yay) => yay,
-//             None => {
-//                 return Err(scroll::Error::BadInput {
-//                     size: 0,
-//                     msg: "Invalid ShaderCodePlatform",
-//                 }
-//                 .into())
-//             }
-//         };
-//         Ok((enum_type, 1))
-//     }
-// }
-//
-impl<'a> TryFromCtx<'a, MinecraftVersion> for ShaderCodePlatform {
+    Unknown(u8),
+}
+/// Platforms in the order of their stored IDs, from the format revision that
+/// introduced each numbering
+const PLATFORM_IDS: [(MinecraftVersion, &[ShaderCodePlatform]); 2] = {
+    use ShaderCodePlatform::*;
+    [
+        (
+            MinecraftVersion::V1_18_30,
+            &[
+                Direct3DSm40,
+                Direct3DSm50,
+                Direct3DSm60,
+                Direct3DSm65,
+                Direct3DXB1,
+                Direct3DXBX,
+                Glsl120,
+                Glsl430,
+                Essl300,
+                Essl310,
+                Metal,
+                Vulkan,
+                Nvn,
+                Pssl,
+            ],
+        ),
+        (MinecraftVersion::V1_21_20, &ShaderCodePlatform::ALL),
+    ]
+};
+/// Bitset lengths a pass can store in `version`. 1.18.30 files have bitsets in either
+/// numbering, their length telling which one
+fn bitset_lengths(version: MinecraftVersion) -> impl Iterator<Item = usize> {
+    let lengths = if version == MinecraftVersion::V1_18_30 {
+        &PLATFORM_IDS[..]
+    } else {
+        &[]
+    };
+    lengths
+        .iter()
+        .map(|(_, platforms)| platforms.len())
+        .chain([ShaderCodePlatform::platforms(version).len()])
+}
+strict_ctx!(ShaderCodePlatform);
+impl<'a> TryFromCtx<'a, ParseOptions> for ShaderCodePlatform {
     type Error = MyError;
     #[inline(never)]
-    fn try_from_ctx(buffer: &'a [u8], ctx: MinecraftVersion) -> Result<(Self, usize), Self::Error> {
+    fn try_from_ctx(buffer: &'a [u8], options: ParseOptions) -> Result<(Self, usize), Self::Error> {
         let int: u8 = buffer.pread_with(0, LE)?;
-        let enum_type = match int {
-            0 => Self::Direct3DSm40,
-            1 => Self::Direct3DSm50,
-            2 => Self::Direct3DSm60,
-            3 => Self::Direct3DSm65,
-            4 => Self::Direct3DXB1,
-            5 => Self::Direct3DXBX,
-            6 => Self::Glsl120,
-            7 => Self::Glsl430,
-            8 => Self::Essl100,
-            9 => Self::Essl300,
-            10 => Self::Essl310,
-            11 => Self::Metal,
-            12 => Self::Vulkan,
-            13 => Self::Nvn,
-            14 => Self::Pssl,
-            _ => {
-                return Err(scroll::Error::BadInput {
-                    size: 0,
-                    msg: "Invalid ShaderCodePlatform",
-                }
-                .into())
+        let enum_type = match Self::from_id(int, options.version) {
+            Some(platform) => platform,
+            None if options.lenient => Self::Unknown(int),
+            None => {
+                return Err(MyError::custom(format!(
+                    "Invalid ShaderCodePlatform for {}: {int}",
+                    options.version
+                )))
             }
         };
         Ok((enum_type, 1))
     }
 }
-impl Display for ShaderCodePlatform {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let name = match self {
-            Self::Direct3DSm40 => "Direct3D_SM40",
-            Self::Direct3DSm50 => "Direct3D_SM50",
-            Self::Direct3DSm60 => "Direct3D_SM60",
-            Self::Direct3DSm65 => "Direct3D_SM65",
-            Self::Direct3DXB1 => "Direct3D_XB1",
-            Self::Direct3DXBX => "Direct3D_XBX",
-            Self::Glsl120 => "GLSL_120",
-            Self::Glsl430 => "GLSL_430",
-            Self::Essl100 => "ESSL_100",
-            Self::Essl300 => "ESSL_300",
-            Self::Essl310 => "ESSL_310",
-            Self::Metal => "Metal",
-            Self::Vulkan => "Vulkan",
-            Self::Nvn => "Nvn",
-            Self::Pssl => "PSSL",
-        };
-        write!(f, "{name}")
+enum_names!(ShaderCodePlatform, "shader platform", {
+    Direct3DSm40 => "Direct3D_SM40",
+    Direct3DSm50 => "Direct3D_SM50",
+    Direct3DSm60 => "Direct3D_SM60",
+    Direct3DSm65 => "Direct3D_SM65",
+    Direct3DXB1 => "Direct3D_XB1",
+    Direct3DXBX => "Direct3D_XBX",
+    Glsl120 => "GLSL_120",
+    Glsl430 => "GLSL_430",
+    Essl100 => "ESSL_100",
+    Essl300 => "ESSL_300",
+    Essl310 => "ESSL_310",
+    Metal => "Metal",
+    Vulkan => "Vulkan",
+    Nvn => "Nvn",
+    Pssl => "PSSL",
+}, Unknown);
+impl ShaderCodePlatform {
+    /// Every known platform, in the numbering of the newest format and the C API
+    pub const ALL: [Self; 15] = [
+        Self::Direct3DSm40,
+        Self::Direct3DSm50,
+        Self::Direct3DSm60,
+        Self::Direct3DSm65,
+        Self::Direct3DXB1,
+        Self::Direct3DXBX,
+        Self::Glsl120,
+        Self::Glsl430,
+        Self::Essl100,
+        Self::Essl300,
+        Self::Essl310,
+        Self::Metal,
+        Self::Vulkan,
+        Self::Nvn,
+        Self::Pssl,
+    ];
+    /// Platforms `version` can store, in the order of their IDs
+    pub fn platforms(version: MinecraftVersion) -> &'static [Self] {
+        PLATFORM_IDS
+            .iter()
+            .rev()
+            .find(|(since, _)| *since <= version)
+            .map_or(&[], |(_, platforms)| platforms)
+    }
+    /// Platform stored as `id` in `version`
+    pub fn from_id(id: u8, version: MinecraftVersion) -> Option<Self> {
+        Self::platforms(version).get(usize::from(id)).copied()
+    }
+    /// ID this platform is stored as in `version`, `None` if the version doesn't have
+    /// it. `Unknown` gives back the ID it was read from
+    pub fn id(self, version: MinecraftVersion) -> Option<u8> {
+        if let Self::Unknown(id) = self {
+            return Some(id);
+        }
+        let index = Self::platforms(version).iter().position(|p| *p == self)?;
+        index.try_into().ok()
+    }
+    /// Position in [`Self::ALL`], `Unknown` gives back the ID it was read from
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Self::Unknown(id) => id,
+            platform => Self::ALL
+                .iter()
+                .position(|p| *p == platform)
+                .and_then(|index| index.try_into().ok())
+                .unwrap_or(u8::MAX),
+        }
     }
 }
-#[derive(Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
 pub struct ShaderCode {
     pub shader_inputs: IndexMap<String, ShaderInput>,
     pub source_hash: u64,
     pub bgfx_shader_data: Vec<u8>,
 }
-impl<'a> TryFromCtx<'a> for ShaderCode {
+impl Hash for ShaderCode {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_unordered(&self.shader_inputs, state);
+        self.source_hash.hash(state);
+        self.bgfx_shader_data.hash(state);
+    }
+}
+impl<'a> TryFromCtx<'a, ParseOptions> for ShaderCode {
     type Error = MyError;
 
-    fn try_from_ctx(buffer: &'a [u8], _: ()) -> Result<(Self, usize), Self::Error> {
+    fn try_from_ctx(buffer: &'a [u8], options: ParseOptions) -> Result<(Self, usize), Self::Error> {
         let mut offset = 0;
         let input_count: u16 = buffer.gread_with(&mut offset, LE)?;
-        let mut shader_inputs = IndexMap::with_capacity(input_count.into());
+        let mut shader_inputs =
+            IndexMap::with_capacity_and_hasher(input_count.into(), Default::default());
         for _ in 0..input_count {
             let name = read_string(buffer, &mut offset)?;
-            let input: ShaderInput = buffer.gread(&mut offset)?;
+            let input: ShaderInput = buffer.gread_with(&mut offset, options)?;
             shader_inputs.insert(name, input);
         }
         let source_hash: u64 = buffer.gread_with(&mut offset, LE)?;
@@ -378,24 +510,28 @@ impl<'a> TryFromCtx<'a> for ShaderCode {
     }
 }
 impl ShaderCode {
+    /// Number of bytes `write` produces
+    pub fn serialized_size(&self) -> Result<usize, WriteError> {
+        serialized_size(|w| self.write(w))
+    }
     pub fn write<W>(&self, writer: &mut W) -> Result<(), WriteError>
     where
-        W: Write,
+        W: ByteWrite,
     {
-        let len = self.shader_inputs.len().try_into()?;
+        let len = checked_len(self.shader_inputs.len(), "shader_inputs")?;
         writer.write_u16::<LittleEndian>(len)?;
         for (name, input) in self.shader_inputs.iter() {
-            write_string(&name, writer)?;
+            write_string(name, writer)?;
             input.write(writer)?;
         }
         writer.write_u64::<LittleEndian>(self.source_hash)?;
-        let len: u32 = self.bgfx_shader_data.len().try_into()?;
+        let len: u32 = checked_len(self.bgfx_shader_data.len(), "bgfx_shader_data")?;
         writer.write_u32::<byteorder::LittleEndian>(len)?;
         writer.write_all(&self.bgfx_shader_data)?;
         Ok(())
     }
 }
-#[derive(Debug)]
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
 pub struct ShaderInput {
     pub input_type: ShaderInputType,
     pub attribute: Attribute,
@@ -404,22 +540,22 @@ pub struct ShaderInput {
     pub interpolation_constraint: Option<InterpolationConstraint>,
 }
 
-impl<'a> TryFromCtx<'a> for ShaderInput {
+impl<'a> TryFromCtx<'a, ParseOptions> for ShaderInput {
     type Error = MyError;
-    fn try_from_ctx(buffer: &'a [u8], _: ()) -> Result<(Self, usize), Self::Error> {
+    fn try_from_ctx(buffer: &'a [u8], options: ParseOptions) -> Result<(Self, usize), Self::Error> {
         let mut offset = 0;
-        let input_type: ShaderInputType = buffer.gread(&mut offset)?;
+        let input_type: ShaderInputType = buffer.gread_with(&mut offset, options)?;
         let attribute: Attribute = buffer.gread(&mut offset)?;
         let is_per_instance = read_bool(buffer, &mut offset)?;
         let mut precision_constraint: Option<PrecisionConstraint> = None;
         let has_precision_constraint = read_bool(buffer, &mut offset)?;
         if has_precision_constraint {
-            precision_constraint = Some(buffer.gread(&mut offset)?);
+            precision_constraint = Some(buffer.gread_with(&mut offset, options)?);
         }
         let mut interpolation_constraint: Option<InterpolationConstraint> = None;
         let has_interpolation_constraint = read_bool(buffer, &mut offset)?;
         if has_interpolation_constraint {
-            interpolation_constraint = Some(buffer.gread(&mut offset)?);
+            interpolation_constraint = Some(buffer.gread_with(&mut offset, options)?);
         }
         Ok((
             Self {
@@ -435,26 +571,29 @@ impl<'a> TryFromCtx<'a> for ShaderInput {
 }
 
 impl ShaderInput {
+    /// Number of bytes `write` produces
+    pub fn serialized_size(&self) -> Result<usize, WriteError> {
+        serialized_size(|w| self.write(w))
+    }
     pub fn write<W>(&self, writer: &mut W) -> Result<(), WriteError>
     where
-        W: Write,
+        W: ByteWrite,
     {
-        writer.write_u8(self.input_type as u8)?;
+        writer.write_u8(self.input_type.to_u8())?;
         let (index, subindex) = self.attribute.to_tuple();
         writer.write_u8(index)?;
         writer.write_u8(subindex)?;
         writer.write_u8(self.is_per_instance as u8)?;
-        optional_write(writer, self.precision_constraint.as_ref(), |o, v| {
-            o.write_u8(*v as u8)
+        optional_write(writer, self.precision_constraint, |o, v| {
+            o.write_u8(v.to_u8())
         })?;
-        optional_write(writer, self.interpolation_constraint.as_ref(), |o, v| {
-            o.write_u8(*v as u8)
+        optional_write(writer, self.interpolation_constraint, |o, v| {
+            o.write_u8(v.to_u8())
         })?;
         Ok(())
     }
 }
-#[derive(Eq, PartialEq, Debug, Clone, Copy)]
-#[repr(u8)]
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Hash)]
 pub enum ShaderInputType {
     Float,
     Vec2,
@@ -469,211 +608,158 @@ pub enum ShaderInputType {
     UInt3,
     UInt4,
     Mat4,
-}
-impl<'a> TryFromCtx<'a> for ShaderInputType {
-    type Error = MyError;
-    fn try_from_ctx(buffer: &'a [u8], _: ()) -> Result<(Self, usize), Self::Error> {
-        let int: u8 = buffer.pread_with(0, LE)?;
-        let enum_type = match int {
-            0 => Self::Float,
-            1 => Self::Vec2,
-            2 => Self::Vec3,
-            3 => Self::Vec4,
-            4 => Self::Int,
-            5 => Self::Int2,
-            6 => Self::Int3,
-            7 => Self::Int4,
-            8 => Self::UInt,
-            9 => Self::UInt2,
-            10 => Self::UInt3,
-            11 => Self::UInt4,
-            12 => Self::Mat4,
-            _ => return Err(scroll::Error::Custom(format!("Invalid ShaderInputType")).into()),
-        };
-        Ok((enum_type, 1))
-    }
-}
+    Unknown(u8),
+}
+raw_enum!(ShaderInputType, u8, to_u8, "shader input type", {
+    Float = 0,
+    Vec2 = 1,
+    Vec3 = 2,
+    Vec4 = 3,
+    Int = 4,
+    Int2 = 5,
+    Int3 = 6,
+    Int4 = 7,
+    UInt = 8,
+    UInt2 = 9,
+    UInt3 = 10,
+    UInt4 = 11,
+    Mat4 = 12,
+});
 
-#[derive(PartialEq, Eq, Debug, Clone, Copy)]
-#[repr(u8)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
 pub enum PrecisionConstraint {
     Low,
     Medium,
     High,
-}
-
-impl<'a> TryFromCtx<'a> for PrecisionConstraint {
-    type Error = MyError;
-    fn try_from_ctx(buffer: &'a [u8], _: ()) -> Result<(Self, usize), Self::Error> {
-        let int: u8 = buffer.pread_with(0, LE)?;
-        let enum_type = match int {
-            0 => Self::Low,
-            1 => Self::Medium,
-            2 => Self::High,
-            _ => {
-                return Err(
-                    scroll::Error::Custom(format!("Invalid PrecisionConstraint: {int}")).into(),
-                )
-            }
-        };
-        Ok((enum_type, 1))
-    }
-}
-#[derive(PartialEq, Eq, Debug, Clone, Copy)]
-#[repr(u8)]
+    Unknown(u8),
+}
+raw_enum!(PrecisionConstraint, u8, to_u8, "precision constraint", {
+    Low = 0,
+    Medium = 1,
+    High = 2,
+});
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
 pub enum InterpolationConstraint {
     Flat,
     Smooth,
     NoPerspective,
     Centroid,
-}
-impl<'a> TryFromCtx<'a> for InterpolationConstraint {
-    type Error = MyError;
-    fn try_from_ctx(buffer: &'a [u8], _: ()) -> Result<(Self, usize), Self::Error> {
-        let int: u8 = buffer.pread_with(0, LE)?;
-        let enum_type = match int {
-            0 => Self::Flat,
-            1 => Self::Smooth,
-            2 => Self::NoPerspective,
-            3 => Self::Centroid,
-            _ => {
-                return Err(scroll::Error::Custom(format!(
-                    "Invalid InterpolationConstraint: {int}"
-                ))
-                .into())
+    Unknown(u8),
+}
+raw_enum!(InterpolationConstraint, u8, to_u8, "interpolation constraint", {
+    Flat = 0,
+    Smooth = 1,
+    NoPerspective = 2,
+    Centroid = 3,
+});
+macro_rules! attributes {
+    ($($variant:ident => ($index:literal, $sub_index:literal), $bgfx:expr, $glsl:expr;)*) => {
+        /// Vertex attribute a shader input is bound to, stored as an `(index, sub_index)` pair
+        #[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+        pub enum Attribute {
+            $($variant,)*
+            /// A pair this crate doesn't know, written back as is
+            Unknown(u8, u8),
+        }
+        impl Attribute {
+            pub fn from_tuple(index: u8, sub_index: u8) -> Self {
+                match (index, sub_index) {
+                    $(($index, $sub_index) => Self::$variant,)*
+                    (index, sub_index) => Self::Unknown(index, sub_index),
+                }
             }
-        };
-        Ok((enum_type, 1))
-    }
-}
-#[derive(Debug)]
-pub enum Attribute {
-    Position,
-    Normal,
-    Tangent,
-    Bitangent,
-    Color0,
-    Color1,
-    Color2,
-    Color3,
-    Indices,
-    Weights,
-    TexCoord0,
-    TexCoord1,
-    TexCoord2,
-    TexCoord3,
-    TexCoord4,
-    TexCoord5,
-    TexCoord6,
-    TexCoord7,
-    TexCoord8,
-    //Unknown_8_0(8, 0),
-    FrontFacing,
+            pub fn to_tuple(self) -> (u8, u8) {
+                match self {
+                    $(Self::$variant => ($index, $sub_index),)*
+                    Self::Unknown(index, sub_index) => (index, sub_index),
+                }
+            }
+            /// Name of the matching `bgfx::Attrib`, if bgfx has one
+            pub fn bgfx_name(self) -> Option<&'static str> {
+                match self {
+                    $(Self::$variant => $bgfx,)*
+                    Self::Unknown(..) => None,
+                }
+            }
+            /// Name of the `a_*` input in bgfx shader source, if the attribute is a vertex input
+            pub fn glsl_name(self) -> Option<&'static str> {
+                match self {
+                    $(Self::$variant => $glsl,)*
+                    Self::Unknown(..) => None,
+                }
+            }
+        }
+    };
+}
+attributes! {
+    Position => (0, 0), Some("Position"), Some("a_position");
+    Normal => (1, 0), Some("Normal"), Some("a_normal");
+    Tangent => (2, 0), Some("Tangent"), Some("a_tangent");
+    Bitangent => (3, 0), Some("Bitangent"), Some("a_bitangent");
+    Color0 => (4, 0), Some("Color0"), Some("a_color0");
+    Color1 => (4, 1), Some("Color1"), Some("a_color1");
+    Color2 => (4, 2), Some("Color2"), Some("a_color2");
+    Color3 => (4, 3), Some("Color3"), Some("a_color3");
+    Indices => (5, 0), Some("Indices"), Some("a_indices");
+    Weights => (6, 0), Some("Weight"), Some("a_weight");
+    TexCoord0 => (7, 0), Some("TexCoord0"), Some("a_texcoord0");
+    TexCoord1 => (7, 1), Some("TexCoord1"), Some("a_texcoord1");
+    TexCoord2 => (7, 2), Some("TexCoord2"), Some("a_texcoord2");
+    TexCoord3 => (7, 3), Some("TexCoord3"), Some("a_texcoord3");
+    TexCoord4 => (7, 4), Some("TexCoord4"), Some("a_texcoord4");
+    TexCoord5 => (7, 5), Some("TexCoord5"), Some("a_texcoord5");
+    TexCoord6 => (7, 6), Some("TexCoord6"), Some("a_texcoord6");
+    TexCoord7 => (7, 7), Some("TexCoord7"), Some("a_texcoord7");
+    // bgfx stops at TexCoord7
+    TexCoord8 => (7, 8), None, Some("a_texcoord8");
+    // gl_FrontFacing, not a vertex input
+    FrontFacing => (9, 0), None, None;
 }
 impl<'a> TryFromCtx<'a> for Attribute {
     type Error = MyError;
     fn try_from_ctx(buffer: &'a [u8], _: ()) -> Result<(Self, usize), Self::Error> {
         let index: u8 = buffer.pread(0)?;
         let sub_index: u8 = buffer.pread(1)?;
-        let enum_type = match (index, sub_index) {
-            (0, 0) => Self::Position,
-            (1, 0) => Self::Normal,
-            (2, 0) => Self::Tangent,
-            (3, 0) => Self::Bitangent,
-            (4, 0) => Self::Color0,
-            (4, 1) => Self::Color1,
-            (4, 2) => Self::Color2,
-            (4, 3) => Self::Color3,
-            (5, 0) => Self::Indices,
-            (6, 0) => Self::Weights,
-            (7, 0) => Self::TexCoord0,
-            (7, 1) => Self::TexCoord1,
-            (7, 2) => Self::TexCoord2,
-            (7, 3) => Self::TexCoord3,
-            (7, 4) => Self::TexCoord4,
-            (7, 5) => Self::TexCoord5,
-            (7, 6) => Self::TexCoord6,
-            (7, 7) => Self::TexCoord7,
-            (7, 8) => Self::TexCoord8,
-            (9, 0) => Self::FrontFacing,
-            _ => {
-                return Err(scroll::Error::Custom(format!(
-                    "Attribute tuple[{:?}] is invalid",
-                    (index, sub_index)
-                ))
-                .into())
-            }
-        };
-        Ok((enum_type, 2))
-    }
-}
-impl Attribute {
-    fn to_tuple(&self) -> (u8, u8) {
-        match self {
-            Self::Position => (0, 0),
-            Self::Normal => (1, 0),
-            Self::Tangent => (2, 0),
-            Self::Bitangent => (3, 0),
-            Self::Color0 => (4, 0),
-            Self::Color1 => (4, 1),
-            Self::Color2 => (4, 2),
-            Self::Color3 => (4, 3),
-            Self::Indices => (5, 0),
-            Self::Weights => (6, 0),
-            Self::TexCoord0 => (7, 0),
-            Self::TexCoord1 => (7, 1),
-            Self::TexCoord2 => (7, 2),
-
-            Self::TexCoord3 => (7, 3),
-            Self::TexCoord4 => (7, 4),
-            Self::TexCoord5 => (7, 5),
-
-            Self::TexCoord6 => (7, 6),
-            Self::TexCoord7 => (7, 7),
-            Self::TexCoord8 => (7, 8),
-            Self::FrontFacing => (9, 0),
-        }
+        Ok((Self::from_tuple(index, sub_index), 2))
     }
 }
 #[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
-#[repr(u8)]
 pub enum ShaderStage {
     Vertex,
     Fragment,
     Compute,
     Unknown,
-}
-
-impl<'a> TryFromCtx<'a> for ShaderStage {
-    type Error = MyError;
-    fn try_from_ctx(buffer: &'a [u8], _: ()) -> Result<(Self, usize), Self::Error> {
-        let int: u8 = buffer.pread_with(0, LE)?;
-        let enum_type = match int {
-            0 => Self::Vertex,
-            1 => Self::Fragment,
-            2 => Self::Compute,
-            3 => Self::Unknown,
-            _ => return Err(scroll::Error::Custom(format!("Invalid ShaderStage: {int}")).into()),
-        };
-        Ok((enum_type, 1))
-    }
-}
-#[derive(Eq, PartialEq, Hash, Debug)]
+    Other(u8),
+}
+raw_enum!(ShaderStage, u8, to_u8, "shader stage", {
+    Vertex = 0,
+    Fragment = 1,
+    Compute = 2,
+    Unknown = 3,
+}, Other);
+enum_names!(ShaderStage, "shader stage", {
+    Vertex => "Vertex",
+    Fragment => "Fragment",
+    Compute => "Compute",
+    Unknown => "Unknown",
+}, Other);
+#[derive(Eq, PartialEq, Hash, Debug, Clone)]
 pub struct PlatformShaderStage {
     pub stage_name: String,
     pub platform_name: String,
     pub stage: ShaderStage,
     pub platform: ShaderCodePlatform,
 }
-impl<'a> TryFromCtx<'a, MinecraftVersion> for PlatformShaderStage {
+strict_ctx!(PlatformShaderStage);
+impl<'a> TryFromCtx<'a, ParseOptions> for PlatformShaderStage {
     type Error = MyError;
 
-    fn try_from_ctx(buffer: &'a [u8], ctx: MinecraftVersion) -> Result<(Self, usize), Self::Error> {
+    fn try_from_ctx(buffer: &'a [u8], options: ParseOptions) -> Result<(Self, usize), Self::Error> {
         let mut offset = 0;
         let stage_name = read_string(buffer, &mut offset)?;
         let platform_name = read_string(buffer, &mut offset)?;
-        let stage: ShaderStage = buffer.gread(&mut offset)?;
-        let platform: ShaderCodePlatform = buffer.gread_with(&mut offset, ctx)?;
+        let stage: ShaderStage = buffer.gread_with(&mut offset, options)?;
+        let platform: ShaderCodePlatform = buffer.gread_with(&mut offset, options)?;
         Ok((
             Self {
                 stage_name,
@@ -686,14 +772,27 @@ impl<'a> TryFromCtx<'a, MinecraftVersion> for PlatformShaderStage {
     }
 }
 impl PlatformShaderStage {
-    pub fn write<W>(&self, writer: &mut W, ctx: MinecraftVersion) -> Result<(), WriteError>
+    /// Number of bytes `write` produces for `version`
+    pub fn serialized_size(&self, version: MinecraftVersion) -> Result<usize, WriteError> {
+        serialized_size(|w| self.write(w, version))
+    }
+    pub fn write<W>(&self, writer: &mut W, version: MinecraftVersion) -> Result<(), WriteError>
     where
-        W: Write,
+        W: ByteWrite,
     {
+        let platform_id = self.platform.id(version).ok_or_else(|| {
+            WriteError::Compat(format!(
+                "{} shaders can't be stored in {version}",
+                self.platform
+            ))
+        })?;
         write_string(&self.stage_name, writer)?;
-        write_string(&self.platform.to_string(), writer)?;
-        writer.write_u8(self.stage as u8)?;
-        writer.write_u8(self.platform as u8)?;
+        match self.platform {
+            ShaderCodePlatform::Unknown(_) => write_string(&self.platform_name, writer)?,
+            platform => write_string(&platform.to_string(), writer)?,
+        }
+        writer.write_u8(self.stage.to_u8())?;
+        writer.write_u8(platform_id)?;
         Ok(())
     }
 }
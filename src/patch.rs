@@ -0,0 +1,89 @@
+use alloc::{string::String, vec::Vec};
+use core::fmt::Display;
+
+use scroll::Pread;
+
+use crate::{
+    bgfx_shader::BgfxShader,
+    pass::{ShaderCodePlatform, ShaderStage},
+    CompiledMaterialDefinition, MyError, WriteError,
+};
+
+/// Replacement shader source for every matching shader code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderPatch {
+    /// Pass to patch, every pass when `None`
+    pub pass: Option<String>,
+    pub stage: ShaderStage,
+    pub platform: ShaderCodePlatform,
+    /// Source code stored inside the bgfx shader, such as ESSL text
+    pub source: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum PatchError {
+    /// The patch at this index matched no shader code
+    NoMatch(usize),
+    Bgfx {
+        pass: String,
+        error: MyError,
+    },
+    Write(WriteError),
+}
+impl core::error::Error for PatchError {}
+impl Display for PatchError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::NoMatch(index) => write!(f, "Patch {index} matched no shader code"),
+            Self::Bgfx { pass, error } => {
+                write!(f, "Bgfx shader of pass {pass} is invalid: {error}")
+            }
+            Self::Write(err) => write!(f, "Writing bgfx shader failed: {err}"),
+        }
+    }
+}
+impl From<WriteError> for PatchError {
+    fn from(value: WriteError) -> Self {
+        Self::Write(value)
+    }
+}
+
+impl CompiledMaterialDefinition {
+    /// Swap the code inside the bgfx shaders matched by `patches`, keeping
+    /// everything else as is. Returns how many shader codes were patched,
+    /// on error the patches before the failing one stay applied
+    pub fn patch_shaders(&mut self, patches: &[ShaderPatch]) -> Result<usize, PatchError> {
+        let mut patched = 0;
+        for (index, patch) in patches.iter().enumerate() {
+            let mut matched = 0;
+            let passes = self
+                .passes
+                .iter_mut()
+                .filter(|(name, _)| patch.pass.as_ref().is_none_or(|pass| pass == *name));
+            for (name, pass) in passes {
+                let codes = pass
+                    .variants
+                    .iter_mut()
+                    .flat_map(|variant| variant.shader_codes.iter_mut())
+                    .filter(|(key, _)| key.stage == patch.stage && key.platform == patch.platform);
+                for (_, code) in codes {
+                    let mut shader: BgfxShader =
+                        code.bgfx_shader_data
+                            .pread(0)
+                            .map_err(|error| PatchError::Bgfx {
+                                pass: name.clone(),
+                                error,
+                            })?;
+                    shader.code.clone_from(&patch.source);
+                    code.bgfx_shader_data = shader.write_vec()?;
+                    matched += 1;
+                }
+            }
+            if matched == 0 {
+                return Err(PatchError::NoMatch(index));
+            }
+            patched += matched;
+        }
+        Ok(patched)
+    }
+}
@@ -1,42 +1,68 @@
-use crate::{common::read_bool, MyError, WriteError};
-use byteorder::WriteBytesExt;
-use scroll::{ctx::TryFromCtx, Pread};
-use std::io::Write;
-#[derive(Debug)]
+use crate::{
+    common::{raw_enum, read_bool, serialized_size},
+    ByteWrite, MyError, ParseOptions, WriteError,
+};
+use alloc::{format, vec::Vec};
+use scroll::{ctx::TryFromCtx, Pread, LE};
+/// Default value and array size of a uniform.
+///
+/// Every type stores `num` and a has-data byte followed by the data, in all supported
+/// versions. That is the layout the original parser read game materials with, only
+/// its writer left both out for external properties. Those have no data, a set
+/// has-data byte is read as none
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
 pub struct PropertyField {
     pub field_type: PropertyType,
     pub num: u32,
+    /// Raw little endian floats of a vec4, use [`PropertyField::value`] to decode them
     pub vector_data: Option<Vec<u8>>,
+    /// Raw little endian floats of a mat3 or mat4, use [`PropertyField::value`] to decode them
     pub matrix_data: Option<Vec<u8>>,
 }
-impl<'a> TryFromCtx<'a> for PropertyField {
+/// Decoded default value of a [`PropertyField`], matrices are in storage order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PropertyValue {
+    Vec4([f32; 4]),
+    Mat3([[f32; 3]; 3]),
+    Mat4([[f32; 4]; 4]),
+    External,
+}
+impl PropertyValue {
+    pub fn property_type(&self) -> PropertyType {
+        match self {
+            Self::Vec4(_) => PropertyType::Vec4,
+            Self::Mat3(_) => PropertyType::Mat3,
+            Self::Mat4(_) => PropertyType::Mat4,
+            Self::External => PropertyType::External,
+        }
+    }
+}
+impl<'a> TryFromCtx<'a, ParseOptions> for PropertyField {
     type Error = MyError;
 
-    fn try_from_ctx(buffer: &'a [u8], _: ()) -> Result<(Self, usize), Self::Error> {
+    fn try_from_ctx(buffer: &'a [u8], options: ParseOptions) -> Result<(Self, usize), Self::Error> {
         let mut offset = 0;
 
-        let field_type: PropertyType = buffer.gread(&mut offset)?;
-        let num: u32 = buffer.gread(&mut offset)?;
+        let field_type: PropertyType = buffer.gread_with(&mut offset, options)?;
+        let num: u32 = buffer.gread_with(&mut offset, LE)?;
         let has_data = read_bool(buffer, &mut offset)?;
         let mut vector_data = None;
         let mut matrix_data = None;
 
-        if has_data {
-            match field_type {
-                PropertyType::Vec4 => {
-                    vector_data = Some(buffer[offset..offset + 16_usize].to_vec());
-                    offset += 16;
-                }
-                PropertyType::Mat3 => {
-                    matrix_data = Some(buffer[offset..offset + 36_usize].to_vec());
-                    offset += 36;
-                }
-                PropertyType::Mat4 => {
-                    matrix_data = Some(buffer[offset..offset + 64_usize].to_vec());
-                    offset += 64;
-                }
-                // We do nothing
-                PropertyType::External => {}
+        let data_len = field_type.data_len();
+        if let PropertyType::Unknown(raw) = field_type {
+            // The layout can only be followed while there is no data of unknown size
+            if has_data {
+                return Err(MyError::custom(format!(
+                    "property type {raw} is unknown and has data of unknown size"
+                )));
+            }
+        } else if data_len != 0 && has_data {
+            let data = buffer.gread_with::<&[u8]>(&mut offset, data_len)?.to_vec();
+            if field_type == PropertyType::Vec4 {
+                vector_data = Some(data);
+            } else {
+                matrix_data = Some(data);
             }
         }
         Ok((
@@ -51,73 +77,106 @@ impl<'a> TryFromCtx<'a> for PropertyField {
     }
 }
 impl PropertyField {
-    pub fn write<W>(&self, writer: &mut W) -> Result<(), WriteError>
-    where
-        W: Write,
-    {
-        writer.write_u16::<byteorder::LittleEndian>(self.field_type.to_u16())?;
-        if self.field_type != PropertyType::External {
-            writer.write_u32::<byteorder::LittleEndian>(self.num)?;
-        }
+    /// Decoded default value, `None` when there is no data or it has the wrong size
+    pub fn value(&self) -> Option<PropertyValue> {
         match self.field_type {
-            PropertyType::Vec4 => {
-                writer.write_u8(self.vector_data.is_some().into())?;
-                if let Some(data) = &self.vector_data {
-                    writer.write_all(data)?;
-                }
-            }
+            PropertyType::Vec4 => decode_floats::<1, 4>(self.vector_data.as_deref()?)
+                .map(|[v]| PropertyValue::Vec4(v)),
             PropertyType::Mat3 => {
-                writer.write_u8(self.matrix_data.is_some().into())?;
-                if let Some(data) = &self.matrix_data {
-                    writer.write_all(data)?;
-                }
+                decode_floats(self.matrix_data.as_deref()?).map(PropertyValue::Mat3)
             }
             PropertyType::Mat4 => {
-                writer.write_u8(self.matrix_data.is_some().into())?;
-                if let Some(data) = &self.matrix_data {
-                    writer.write_all(data)?;
-                }
+                decode_floats(self.matrix_data.as_deref()?).map(PropertyValue::Mat4)
+            }
+            PropertyType::External => Some(PropertyValue::External),
+            PropertyType::Unknown(_) => None,
+        }
+    }
+    /// Replace the default value, changing `field_type` to match it
+    pub fn set_value(&mut self, value: PropertyValue) {
+        self.field_type = value.property_type();
+        (self.vector_data, self.matrix_data) = match value {
+            PropertyValue::Vec4(v) => (Some(encode_floats(&[v])), None),
+            PropertyValue::Mat3(m) => (None, Some(encode_floats(&m))),
+            PropertyValue::Mat4(m) => (None, Some(encode_floats(&m))),
+            PropertyValue::External => (None, None),
+        };
+    }
+    /// Remove the default value, keeping the type
+    pub fn clear_value(&mut self) {
+        self.vector_data = None;
+        self.matrix_data = None;
+    }
+    /// Number of bytes `write` produces
+    pub fn serialized_size(&self) -> Result<usize, WriteError> {
+        serialized_size(|w| self.write(w))
+    }
+    pub fn write<W>(&self, writer: &mut W) -> Result<(), WriteError>
+    where
+        W: ByteWrite,
+    {
+        writer.write_u16::<byteorder::LittleEndian>(self.field_type.to_u16())?;
+        writer.write_u32::<byteorder::LittleEndian>(self.num)?;
+        let data = match self.field_type {
+            PropertyType::Vec4 => &self.vector_data,
+            PropertyType::Mat3 | PropertyType::Mat4 => &self.matrix_data,
+            PropertyType::External | PropertyType::Unknown(_) => &None,
+        };
+        writer.write_u8(data.is_some().into())?;
+        if let Some(data) = data {
+            let expected = self.field_type.data_len();
+            if data.len() != expected {
+                return Err(WriteError::Compat(format!(
+                    "{:?} property data is {} bytes instead of {expected}",
+                    self.field_type,
+                    data.len()
+                )));
             }
-            // We do absolutely nothing
-            PropertyType::External => {}
+            writer.write_all(data)?;
         }
         Ok(())
     }
 }
-#[derive(Debug, PartialEq)]
+fn decode_floats<const R: usize, const C: usize>(data: &[u8]) -> Option<[[f32; C]; R]> {
+    if data.len() != R * C * 4 {
+        return None;
+    }
+    let mut floats = data
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    Some(core::array::from_fn(|_| {
+        core::array::from_fn(|_| floats.next().unwrap_or_default())
+    }))
+}
+fn encode_floats<const C: usize>(rows: &[[f32; C]]) -> Vec<u8> {
+    rows.iter()
+        .flatten()
+        .flat_map(|f| f.to_le_bytes())
+        .collect()
+}
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum PropertyType {
     Vec4,
     Mat3,
     Mat4,
     External,
+    /// Only fields without a default value can be read
+    Unknown(u16),
 }
-impl<'a> TryFromCtx<'a> for PropertyType {
-    type Error = MyError;
-
-    fn try_from_ctx(buffer: &'a [u8], _: ()) -> Result<(Self, usize), Self::Error> {
-        let property_type: u16 = buffer.pread(0)?;
-        let enum_type = match property_type {
-            2 => Self::Vec4,
-            3 => Self::Mat3,
-            4 => Self::Mat4,
-            5 => Self::External,
-            _ => {
-                return Err(scroll::Error::Custom(format!(
-                    "property type is invalid: {property_type}"
-                ))
-                .into())
-            }
-        };
-        Ok((enum_type, 2))
-    }
-}
+raw_enum!(PropertyType, u16, to_u16, "property type", {
+    Vec4 = 2,
+    Mat3 = 3,
+    Mat4 = 4,
+    External = 5,
+});
 impl PropertyType {
-    pub fn to_u16(&self) -> u16 {
+    /// Size of the default value, external properties have none
+    fn data_len(&self) -> usize {
         match self {
-            Self::Vec4 => 2,
-            Self::Mat3 => 3,
-            Self::Mat4 => 4,
-            Self::External => 5,
+            Self::Vec4 => 16,
+            Self::Mat3 => 36,
+            Self::Mat4 => 64,
+            Self::External | Self::Unknown(_) => 0,
         }
     }
 }
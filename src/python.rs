@@ -0,0 +1,377 @@
+use std::ffi::CString;
+
+use pyo3::{
+    exceptions::{PyIndexError, PyUserWarning, PyValueError},
+    prelude::*,
+    types::{PyBytes, PyDict},
+};
+use scroll::Pread;
+
+use crate::{
+    pass::{Pass, ShaderCode, ShaderCodePlatform, Variant},
+    uniform_override::lost_overrides_message,
+    CompiledMaterialDefinition, MinecraftVersion, ParseOptions, ALL_VERSIONS,
+};
+
+macro_rules! py_enum {
+    ($py:ident, $name:literal, $rust:ty, [$($variant:ident),* $(,)?]) => {
+        py_enum!(@define $py, $name, $rust, [$($variant),*]);
+        impl From<$rust> for $py {
+            fn from(value: $rust) -> Self {
+                match value {
+                    $(<$rust>::$variant => $py::$variant,)*
+                }
+            }
+        }
+    };
+    // Rust enums with an `Unknown(raw)` variant, which has no Python equivalent
+    ($py:ident, $name:literal, $rust:ty, [$($variant:ident),* $(,)?], Unknown) => {
+        py_enum!(@define $py, $name, $rust, [$($variant),*]);
+        impl TryFrom<$rust> for $py {
+            type Error = PyErr;
+            fn try_from(value: $rust) -> PyResult<Self> {
+                match value {
+                    $(<$rust>::$variant => Ok($py::$variant),)*
+                    unknown => Err(value_error(format!(
+                        "{unknown} has no Python equivalent"
+                    ))),
+                }
+            }
+        }
+    };
+    (@define $py:ident, $name:literal, $rust:ty, [$($variant:ident),*]) => {
+        #[pyclass(eq, eq_int, name = $name, from_py_object)]
+        #[derive(Clone, Copy, PartialEq)]
+        #[allow(non_camel_case_types)]
+        enum $py {
+            $($variant,)*
+        }
+        impl From<$py> for $rust {
+            fn from(value: $py) -> Self {
+                match value {
+                    $($py::$variant => <$rust>::$variant,)*
+                }
+            }
+        }
+        #[pymethods]
+        impl $py {
+            fn __str__(&self) -> String {
+                <$rust>::from(*self).to_string()
+            }
+        }
+    };
+}
+py_enum!(
+    PyMinecraftVersion,
+    "MinecraftVersion",
+    MinecraftVersion,
+    [V1_18_30, V1_19_60, V1_20_80, V1_21_20, V1_21_110, V26_0_24, V26_10_20]
+);
+py_enum!(
+    PyShaderCodePlatform,
+    "ShaderCodePlatform",
+    ShaderCodePlatform,
+    [
+        Direct3DSm40,
+        Direct3DSm50,
+        Direct3DSm60,
+        Direct3DSm65,
+        Direct3DXB1,
+        Direct3DXBX,
+        Glsl120,
+        Glsl430,
+        Essl100,
+        Essl300,
+        Essl310,
+        Metal,
+        Vulkan,
+        Nvn,
+        Pssl,
+    ],
+    Unknown
+);
+
+fn value_error(error: impl std::fmt::Display) -> PyErr {
+    PyValueError::new_err(error.to_string())
+}
+fn parse(
+    data: &[u8],
+    version: Option<PyMinecraftVersion>,
+    lenient: bool,
+) -> PyResult<(CompiledMaterialDefinition, MinecraftVersion)> {
+    let options = |version| ParseOptions { version, lenient };
+    if let Some(version) = version {
+        let version = version.into();
+        let material = data.pread_with(0, options(version)).map_err(value_error)?;
+        return Ok((material, version));
+    }
+    let mut last_error = None;
+    for version in ALL_VERSIONS.into_iter().rev() {
+        match data.pread_with(0, options(version)) {
+            Ok(material) => return Ok((material, version)),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(value_error(last_error.map_or_else(
+        || "No version could parse the material".to_string(),
+        |e| e.to_string(),
+    )))
+}
+
+/// A parsed material
+#[pyclass(name = "Material")]
+struct PyMaterial {
+    definition: CompiledMaterialDefinition,
+    version: MinecraftVersion,
+}
+#[pymethods]
+impl PyMaterial {
+    /// Parse a material, trying every known version when `version` is None. With
+    /// `lenient`, unknown enum values are kept instead of failing
+    #[staticmethod]
+    #[pyo3(signature = (data, version=None, lenient=false))]
+    fn parse(data: &[u8], version: Option<PyMinecraftVersion>, lenient: bool) -> PyResult<Self> {
+        let (definition, version) = parse(data, version, lenient)?;
+        Ok(Self {
+            definition,
+            version,
+        })
+    }
+    /// Serialize the material, as the version it was parsed with when `version` is None
+    #[pyo3(signature = (version=None))]
+    fn write<'py>(
+        &self,
+        py: Python<'py>,
+        version: Option<PyMinecraftVersion>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let version = version.map_or(self.version, Into::into);
+        let output = self.definition.write_vec(version).map_err(value_error)?;
+        Ok(PyBytes::new(py, &output))
+    }
+    /// Version the material was parsed with
+    #[getter]
+    fn version(&self) -> PyMinecraftVersion {
+        self.version.into()
+    }
+    #[getter]
+    fn name(&self) -> &str {
+        &self.definition.name
+    }
+    #[getter]
+    fn parent_name(&self) -> Option<&str> {
+        self.definition.parent_name.as_deref()
+    }
+    fn pass_names(&self) -> Vec<String> {
+        self.definition.passes.keys().cloned().collect()
+    }
+    fn passes(slf: Bound<'_, Self>) -> Vec<PyPass> {
+        let names = slf.borrow().pass_names();
+        names
+            .into_iter()
+            .map(|name| PyPass {
+                material: slf.clone().unbind(),
+                name,
+            })
+            .collect()
+    }
+    fn __repr__(&self) -> String {
+        format!(
+            "<Material {} ({}), {} passes>",
+            self.definition.name,
+            self.version,
+            self.definition.passes.len()
+        )
+    }
+}
+
+/// Pass of a material, reads and writes go to the material it came from
+#[pyclass(name = "Pass")]
+struct PyPass {
+    material: Py<PyMaterial>,
+    name: String,
+}
+impl PyPass {
+    fn with<T>(&self, py: Python<'_>, f: impl FnOnce(&mut Pass) -> T) -> PyResult<T> {
+        let mut material = self.material.borrow_mut(py);
+        let pass = material
+            .definition
+            .passes
+            .get_mut(&self.name)
+            .ok_or_else(|| PyIndexError::new_err(format!("Pass {} was removed", self.name)))?;
+        Ok(f(pass))
+    }
+}
+#[pymethods]
+impl PyPass {
+    #[getter]
+    fn name(&self) -> &str {
+        &self.name
+    }
+    #[getter]
+    fn bitset(&self, py: Python<'_>) -> PyResult<String> {
+        self.with(py, |pass| pass.bitset.clone())
+    }
+    #[getter]
+    fn fallback(&self, py: Python<'_>) -> PyResult<String> {
+        self.with(py, |pass| pass.fallback.clone())
+    }
+    #[getter]
+    fn blend_mode(&self, py: Python<'_>) -> PyResult<Option<String>> {
+        self.with(py, |pass| {
+            pass.default_blendmode.as_ref().map(ToString::to_string)
+        })
+    }
+    #[getter]
+    fn framebuffer_binding(&self, py: Python<'_>) -> PyResult<Option<u32>> {
+        self.with(py, |pass| pass.framebuffer_binding)
+    }
+    /// Summary of the pass's blend equation and framebuffer
+    #[getter]
+    fn render_state(&self, py: Python<'_>) -> PyResult<String> {
+        self.with(py, |pass| pass.render_state().to_string())
+    }
+    fn variants(&self, py: Python<'_>) -> PyResult<Vec<PyVariant>> {
+        let count = self.with(py, |pass| pass.variants.len())?;
+        Ok((0..count)
+            .map(|index| PyVariant {
+                material: self.material.clone_ref(py),
+                pass: self.name.clone(),
+                index,
+            })
+            .collect())
+    }
+    fn __repr__(&self) -> String {
+        format!("<Pass {}>", self.name)
+    }
+}
+
+#[pyclass(name = "Variant")]
+struct PyVariant {
+    material: Py<PyMaterial>,
+    pass: String,
+    index: usize,
+}
+impl PyVariant {
+    fn with<T>(&self, py: Python<'_>, f: impl FnOnce(&mut Variant) -> T) -> PyResult<T> {
+        let mut material = self.material.borrow_mut(py);
+        let variant = material
+            .definition
+            .passes
+            .get_mut(&self.pass)
+            .and_then(|pass| pass.variants.get_mut(self.index))
+            .ok_or_else(|| {
+                PyIndexError::new_err(format!(
+                    "Variant {} of pass {} was removed",
+                    self.index, self.pass
+                ))
+            })?;
+        Ok(f(variant))
+    }
+}
+#[pymethods]
+impl PyVariant {
+    #[getter]
+    fn is_supported(&self, py: Python<'_>) -> PyResult<bool> {
+        self.with(py, |variant| variant.is_supported)
+    }
+    #[getter]
+    fn flags<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let flags = self.with(py, |variant| variant.flags.clone())?;
+        let dict = PyDict::new(py);
+        for (key, value) in flags {
+            dict.set_item(key, value)?;
+        }
+        Ok(dict)
+    }
+    fn shader_codes(&self, py: Python<'_>) -> PyResult<Vec<PyShaderCode>> {
+        let count = self.with(py, |variant| variant.shader_codes.len())?;
+        Ok((0..count)
+            .map(|index| PyShaderCode {
+                material: self.material.clone_ref(py),
+                pass: self.pass.clone(),
+                variant: self.index,
+                index,
+            })
+            .collect())
+    }
+}
+
+/// Shader blob of one platform and stage in a variant
+#[pyclass(name = "ShaderCode")]
+struct PyShaderCode {
+    material: Py<PyMaterial>,
+    pass: String,
+    variant: usize,
+    index: usize,
+}
+impl PyShaderCode {
+    fn with<T>(
+        &self,
+        py: Python<'_>,
+        f: impl FnOnce(ShaderCodePlatform, &str, &mut ShaderCode) -> T,
+    ) -> PyResult<T> {
+        let mut material = self.material.borrow_mut(py);
+        let (key, code) = material
+            .definition
+            .passes
+            .get_mut(&self.pass)
+            .and_then(|pass| pass.variants.get_mut(self.variant))
+            .and_then(|variant| variant.shader_codes.get_index_mut(self.index))
+            .ok_or_else(|| PyIndexError::new_err("Shader code was removed"))?;
+        Ok(f(key.platform, &key.stage_name, code))
+    }
+}
+#[pymethods]
+impl PyShaderCode {
+    #[getter]
+    fn platform(&self, py: Python<'_>) -> PyResult<PyShaderCodePlatform> {
+        self.with(py, |platform, _, _| platform.try_into())?
+    }
+    #[getter]
+    fn stage(&self, py: Python<'_>) -> PyResult<String> {
+        self.with(py, |_, stage, _| stage.to_string())
+    }
+    #[getter]
+    fn source_hash(&self, py: Python<'_>) -> PyResult<u64> {
+        self.with(py, |_, _, code| code.source_hash)
+    }
+    #[getter]
+    fn bgfx_shader_data<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let data = self.with(py, |_, _, code| code.bgfx_shader_data.clone())?;
+        Ok(PyBytes::new(py, &data))
+    }
+    #[setter]
+    fn set_bgfx_shader_data(&self, py: Python<'_>, data: &[u8]) -> PyResult<()> {
+        self.with(py, |_, _, code| code.bgfx_shader_data = data.to_vec())
+    }
+}
+
+/// Convert a material to `version`, returning the converted bytes. Uniform overrides
+/// `version` can't store are dropped with a `UserWarning`
+#[pyfunction]
+fn update<'py>(
+    py: Python<'py>,
+    data: &[u8],
+    version: PyMinecraftVersion,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let (mut material, _) = parse(data, None, false)?;
+    let lost = material.inline_uniform_overrides(version.into(), None);
+    if !lost.is_empty() {
+        let message = CString::new(lost_overrides_message(&lost)).map_err(value_error)?;
+        PyErr::warn(py, &py.get_type::<PyUserWarning>(), &message, 1)?;
+    }
+    let output = material.write_vec(version.into()).map_err(value_error)?;
+    Ok(PyBytes::new(py, &output))
+}
+
+#[pymodule]
+fn materialbin(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyMinecraftVersion>()?;
+    m.add_class::<PyShaderCodePlatform>()?;
+    m.add_class::<PyMaterial>()?;
+    m.add_class::<PyPass>()?;
+    m.add_class::<PyVariant>()?;
+    m.add_class::<PyShaderCode>()?;
+    m.add_function(wrap_pyfunction!(update, m)?)?;
+    Ok(())
+}
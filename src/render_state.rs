@@ -0,0 +1,156 @@
+use core::fmt::Display;
+
+use crate::pass::{BlendMode, Pass};
+
+/// Factor a blend equation multiplies the source or destination color with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlendFactor {
+    Zero,
+    One,
+    SrcColor,
+    InvSrcColor,
+    SrcAlpha,
+    InvSrcAlpha,
+    DstColor,
+    InvDstColor,
+}
+
+/// `src * src_factor + dst * dst_factor`, with separate factors for color and alpha
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlendEquation {
+    pub src_color: BlendFactor,
+    pub dst_color: BlendFactor,
+    pub src_alpha: BlendFactor,
+    pub dst_alpha: BlendFactor,
+}
+impl BlendEquation {
+    const fn same(src: BlendFactor, dst: BlendFactor) -> Self {
+        Self {
+            src_color: src,
+            dst_color: dst,
+            src_alpha: src,
+            dst_alpha: dst,
+        }
+    }
+}
+impl Display for BlendEquation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "src * {:?} + dst * {:?}", self.src_color, self.dst_color)?;
+        if (self.src_alpha, self.dst_alpha) != (self.src_color, self.dst_color) {
+            write!(
+                f,
+                ", alpha src * {:?} + dst * {:?}",
+                self.src_alpha, self.dst_alpha
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl BlendMode {
+    /// Blend equation this mode stands for, `None` when blending is off or the mode is
+    /// unknown. The game's own table isn't public: modes with a bgfx preset use its
+    /// factors (`BGFX_STATE_BLEND_*` in bgfx.h), the others are read from the mode name
+    pub fn equation(&self) -> Option<BlendEquation> {
+        use BlendFactor::*;
+        let equation = match self {
+            Self::None => return None,
+            Self::Replace => BlendEquation::same(One, Zero),
+            // BGFX_STATE_BLEND_ALPHA
+            Self::AlphaBlend => BlendEquation::same(SrcAlpha, InvSrcAlpha),
+            Self::ColorBlendAlphaAdd => BlendEquation {
+                src_color: SrcAlpha,
+                dst_color: InvSrcAlpha,
+                src_alpha: One,
+                dst_alpha: One,
+            },
+            // BGFX_STATE_BLEND_NORMAL
+            Self::PreMultiplied => BlendEquation::same(One, InvSrcAlpha),
+            Self::InvertColor => BlendEquation::same(InvDstColor, InvSrcColor),
+            // BGFX_STATE_BLEND_ADD
+            Self::Additive => BlendEquation::same(One, One),
+            Self::AdditiveAlpha => BlendEquation::same(SrcAlpha, One),
+            // BGFX_STATE_BLEND_MULTIPLY
+            Self::Multiply => BlendEquation::same(DstColor, Zero),
+            Self::MultiplyBoth => BlendEquation::same(DstColor, SrcColor),
+            Self::InverseSrcAlpha => BlendEquation::same(Zero, InvSrcAlpha),
+            Self::SrcAlpha => BlendEquation::same(Zero, SrcAlpha),
+            Self::Unknown(_) => return None,
+        };
+        Some(equation)
+    }
+}
+
+/// Decoded [`Pass::framebuffer_binding`]. Only binding 0 has a known meaning, any
+/// other value is kept as is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FramebufferBinding {
+    /// Binding 0, the render target of the stage the pass is drawn in
+    Default,
+    /// A binding whose meaning isn't known yet
+    Other(u32),
+}
+impl From<u32> for FramebufferBinding {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => Self::Default,
+            other => Self::Other(other),
+        }
+    }
+}
+impl From<FramebufferBinding> for u32 {
+    fn from(value: FramebufferBinding) -> Self {
+        match value {
+            FramebufferBinding::Default => 0,
+            FramebufferBinding::Other(other) => other,
+        }
+    }
+}
+impl Display for FramebufferBinding {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::Other(binding) => write!(f, "binding {binding}"),
+        }
+    }
+}
+
+/// How a pass renders, as far as the material describes it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RenderState {
+    pub blend_mode: Option<BlendMode>,
+    pub blend: Option<BlendEquation>,
+    /// `None` before 26.0.24
+    pub framebuffer: Option<FramebufferBinding>,
+}
+impl Display for RenderState {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match (self.blend_mode, self.blend) {
+            (Some(mode), Some(blend)) => write!(f, "blend {mode} ({blend})")?,
+            (Some(mode @ BlendMode::Unknown(_)), None) => write!(f, "blend {mode}")?,
+            (Some(mode), None) => write!(f, "blend {mode} (off)")?,
+            (None, _) => write!(f, "blend inherited")?,
+        }
+        if let Some(framebuffer) = self.framebuffer {
+            write!(f, ", framebuffer {framebuffer}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Pass {
+    pub fn framebuffer(&self) -> Option<FramebufferBinding> {
+        self.framebuffer_binding.map(Into::into)
+    }
+    pub fn set_framebuffer(&mut self, binding: Option<FramebufferBinding>) {
+        self.framebuffer_binding = binding.map(Into::into);
+    }
+    /// Summary of the render state set by this pass
+    pub fn render_state(&self) -> RenderState {
+        RenderState {
+            blend_mode: self.default_blendmode,
+            blend: self.default_blendmode.and_then(|mode| mode.equation()),
+            framebuffer: self.framebuffer(),
+        }
+    }
+}
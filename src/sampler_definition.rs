@@ -1,32 +1,46 @@
-use std::io::Write;
-
-use byteorder::{LittleEndian, WriteBytesExt};
+use alloc::{
+    borrow::ToOwned,
+    format,
+    string::{String, ToString},
+};
+use byteorder::LittleEndian;
+use core::fmt::Display;
 use scroll::{ctx::TryFromCtx, Pread, LE};
 
 use crate::{
-    common::{optional_write, read_bool, read_string, write_string},
-    MinecraftVersion, MyError, WriteError,
+    common::{
+        checked_len, enum_names, optional_write, read_bool, read_string, serialized_size,
+        strict_ctx, write_string,
+    },
+    ByteWrite, MinecraftVersion, MyError, ParseOptions, WriteError,
 };
 
-#[derive(Debug)]
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
 pub struct SamplerDefinition {
     pub reg: u16,
     pub access: SamplerAccess,
     pub precision: Precision,
     pub allow_unordered_access: u8,
     pub sampler_type: SamplerType,
+    /// Format of image samplers, empty otherwise. See [`Self::format`]
     pub texture_format: String,
+    /// Meaning unknown, kept raw so it writes back unchanged
     pub unknown_int: u32,
+    /// Not stored in 1.18.30, where it's the same as `reg`. Kept raw like `unknown_int`
     pub unknown_byte: u8,
+    /// Raw sampler state, 1.21.20+. See [`Self::state`]
     pub sampler_state: Option<u8>,
 
     pub default_texture: Option<String>,
+    /// 1.20.80+, meaning unknown
     pub unknown_string: Option<String>,
     pub custom_type_info: Option<CustomTypeInfo>,
 }
-impl<'a> TryFromCtx<'a, MinecraftVersion> for SamplerDefinition {
+strict_ctx!(SamplerDefinition);
+impl<'a> TryFromCtx<'a, ParseOptions> for SamplerDefinition {
     type Error = MyError;
-    fn try_from_ctx(buffer: &'a [u8], ctx: MinecraftVersion) -> Result<(Self, usize), Self::Error> {
+    fn try_from_ctx(buffer: &'a [u8], options: ParseOptions) -> Result<(Self, usize), Self::Error> {
+        let ctx = options.version;
         let mut offset = 0;
         let reg: u16 = if ctx == MinecraftVersion::V1_18_30 {
             buffer.gread::<u8>(&mut offset)?.into()
@@ -36,7 +50,7 @@ impl<'a> TryFromCtx<'a, MinecraftVersion> for SamplerDefinition {
         let access: SamplerAccess = buffer.gread_with(&mut offset, ())?;
         let precision: Precision = buffer.gread_with(&mut offset, ())?;
         let allow_unordered_access: u8 = buffer.gread_with(&mut offset, LE)?;
-        let sampler_type: SamplerType = buffer.gread_with(&mut offset, ctx)?;
+        let sampler_type: SamplerType = buffer.gread_with(&mut offset, options)?;
         let texture_format = read_string(buffer, &mut offset)?;
 
         let unknown_int: u32 = buffer.gread_with(&mut offset, LE)?;
@@ -44,13 +58,11 @@ impl<'a> TryFromCtx<'a, MinecraftVersion> for SamplerDefinition {
             buffer.gread_with(&mut offset, LE)?
         } else {
             reg.try_into()
-                .map_err(|e| scroll::Error::Custom(format!("unknown byte parsing error: {e}")))?
+                .map_err(|e| MyError::custom(format!("unknown byte parsing error: {e}")))?
         };
         let mut sampler_state = None;
-        if ctx >= MinecraftVersion::V1_21_20 {
-            if read_bool(buffer, &mut offset)? {
-                sampler_state = Some(buffer.gread::<u8>(&mut offset)?);
-            }
+        if ctx >= MinecraftVersion::V1_21_20 && read_bool(buffer, &mut offset)? {
+            sampler_state = Some(buffer.gread::<u8>(&mut offset)?);
         }
         let mut default_texture = None;
         let has_default_texture = read_bool(buffer, &mut offset)?;
@@ -91,12 +103,35 @@ impl<'a> TryFromCtx<'a, MinecraftVersion> for SamplerDefinition {
     }
 }
 impl SamplerDefinition {
+    /// Decoded `texture_format`, `None` when the sampler has no format
+    pub fn format(&self) -> Option<TextureFormat> {
+        (!self.texture_format.is_empty()).then(|| TextureFormat::from_name(&self.texture_format))
+    }
+    pub fn set_format(&mut self, format: Option<TextureFormat>) {
+        self.texture_format = format.map(|f| f.name().to_owned()).unwrap_or_default();
+    }
+    /// Decoded `sampler_state`
+    pub fn state(&self) -> Option<SamplerState> {
+        self.sampler_state.map(Into::into)
+    }
+    pub fn set_state(&mut self, state: Option<SamplerState>) {
+        self.sampler_state = state.map(Into::into);
+    }
+    /// Whether a shadow sampler is given a format that isn't a depth format.
+    /// Samplers without a format aren't checked
+    pub fn has_format_mismatch(&self) -> bool {
+        self.sampler_type.is_shadow() && self.format().is_some_and(|f| !f.is_depth())
+    }
+    /// Number of bytes `write` produces for `version`
+    pub fn serialized_size(&self, version: MinecraftVersion) -> Result<usize, WriteError> {
+        serialized_size(|w| self.write(w, version))
+    }
     pub fn write<W>(&self, writer: &mut W, version: MinecraftVersion) -> Result<(), WriteError>
     where
-        W: Write,
+        W: ByteWrite,
     {
         if version == MinecraftVersion::V1_18_30 {
-            writer.write_u8(self.reg.try_into()?)?;
+            writer.write_u8(checked_len(self.reg.into(), "reg")?)?;
         } else {
             writer.write_u16::<LittleEndian>(self.reg)?;
         }
@@ -125,7 +160,7 @@ impl SamplerDefinition {
         Ok(())
     }
 }
-#[derive(Debug)]
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
 pub struct CustomTypeInfo {
     pub name: String,
     pub size: u32,
@@ -140,9 +175,13 @@ impl<'a> TryFromCtx<'a> for CustomTypeInfo {
     }
 }
 impl CustomTypeInfo {
+    /// Number of bytes `write` produces
+    pub fn serialized_size(&self) -> Result<usize, WriteError> {
+        serialized_size(|w| self.write(w))
+    }
     pub fn write<W>(&self, writer: &mut W) -> Result<(), WriteError>
     where
-        W: Write,
+        W: ByteWrite,
     {
         write_string(&self.name, writer)?;
         writer.write_u32::<LittleEndian>(self.size)?;
@@ -150,7 +189,7 @@ impl CustomTypeInfo {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
 pub enum SamplerType {
     Type2D,
     Type2DArray,
@@ -163,14 +202,16 @@ pub enum SamplerType {
     TypeAccelerationStructure,
     Type2DShadow,
     Type2DArrayShadow,
+    /// Holds the value as stored, before the renumbering of 1.21.20
+    Unknown(u8),
 }
-impl<'a> TryFromCtx<'a, MinecraftVersion> for SamplerType {
+strict_ctx!(SamplerType);
+impl<'a> TryFromCtx<'a, ParseOptions> for SamplerType {
     type Error = MyError;
-    fn try_from_ctx(
-        buffer: &'a [u8],
-        version: MinecraftVersion,
-    ) -> Result<(Self, usize), Self::Error> {
-        let mut sampler_type: u8 = buffer.pread_with(0, LE)?;
+    fn try_from_ctx(buffer: &'a [u8], options: ParseOptions) -> Result<(Self, usize), Self::Error> {
+        let version = options.version;
+        let raw: u8 = buffer.pread_with(0, LE)?;
+        let mut sampler_type = raw;
         // On versions before 1.21.20, 5 is Structured Buffer
         // After 1.21.20, 5 is SamplerCubeArray
         // Adjust the difference.
@@ -189,17 +230,37 @@ impl<'a> TryFromCtx<'a, MinecraftVersion> for SamplerType {
             8 => Self::TypeAccelerationStructure,
             9 => Self::Type2DShadow,
             10 => Self::Type2DArrayShadow,
+            _ if options.lenient => Self::Unknown(raw),
             _ => {
-                return Err(
-                    scroll::Error::Custom(format!("Invalid sapmler_type: {sampler_type}")).into(),
-                )
+                return Err(MyError::custom(format!(
+                    "Invalid sapmler_type: {sampler_type}"
+                )))
             }
         };
         Ok((enum_sub, 1))
     }
 }
+enum_names!(SamplerType, "sampler type", {
+    Type2D => "2D",
+    Type2DArray => "2DArray",
+    Type2DExternal => "2DExternal",
+    Type3D => "3D",
+    TypeCube => "Cube",
+    TypeSamplerCubeArray => "CubeArray",
+    TypeStructuredBuffer => "StructuredBuffer",
+    TypeRawBuffer => "RawBuffer",
+    TypeAccelerationStructure => "AccelerationStructure",
+    Type2DShadow => "2DShadow",
+    Type2DArrayShadow => "2DArrayShadow",
+}, Unknown);
 impl SamplerType {
+    pub fn is_shadow(self) -> bool {
+        matches!(self, Self::Type2DShadow | Self::Type2DArrayShadow)
+    }
     fn to_u8(self, version: MinecraftVersion) -> Result<u8, WriteError> {
+        if let Self::Unknown(raw) = self {
+            return Ok(raw);
+        }
         if version < MinecraftVersion::V1_21_20 {
             return match self {
                 Self::TypeSamplerCubeArray => Err(WriteError::Compat("Sampler type is (Sampler Cube Array) ,which is incompatible with versions before 1.21.20".to_string())),
@@ -207,20 +268,37 @@ impl SamplerType {
                 Self::TypeAccelerationStructure => Ok(6),
                 Self::Type2DShadow => Ok(7),
                 Self::Type2DArrayShadow => Ok(8),
-                _ => Ok(self as u8),
+                _ => Ok(self.index()),
             };
         }
-        Ok(self as u8)
+        Ok(self.index())
+    }
+    /// Position in the 1.21.20+ numbering
+    fn index(self) -> u8 {
+        match self {
+            Self::Type2D => 0,
+            Self::Type2DArray => 1,
+            Self::Type2DExternal => 2,
+            Self::Type3D => 3,
+            Self::TypeCube => 4,
+            Self::TypeSamplerCubeArray => 5,
+            Self::TypeStructuredBuffer => 6,
+            Self::TypeRawBuffer => 7,
+            Self::TypeAccelerationStructure => 8,
+            Self::Type2DShadow => 9,
+            Self::Type2DArrayShadow => 10,
+            Self::Unknown(raw) => raw,
+        }
     }
 }
-#[derive(Debug)]
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
 pub enum SamplerAccess {
     None,
     Read,
     Write,
     ReadWrite,
 }
-#[derive(Debug, Clone, Copy)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
 pub enum Precision {
     Low,
     Medium,
@@ -254,7 +332,7 @@ impl<'a> TryFromCtx<'a> for SamplerAccess {
             1 => Ok((Self::Read, 1)),
             2 => Ok((Self::Write, 1)),
             3 => Ok((Self::ReadWrite, 1)),
-            _ => Err(scroll::Error::Custom("Sampler Access is not valid".to_owned()).into()),
+            _ => Err(MyError::custom("Sampler Access is not valid".to_owned())),
         }
     }
 }
@@ -268,3 +346,128 @@ impl SamplerAccess {
         }
     }
 }
+
+macro_rules! texture_formats {
+    ($($variant:ident => $name:literal $(depth = $depth:literal)?,)*) => {
+        /// Value of [`SamplerDefinition::texture_format`]
+        #[derive(PartialEq, Eq, Debug, Clone, Hash)]
+        pub enum TextureFormat {
+            $($variant,)*
+            /// A format this crate doesn't know, kept as written
+            Unknown(String),
+        }
+        impl TextureFormat {
+            /// Parse a format name, ignoring case
+            pub fn from_name(name: &str) -> Self {
+                $(if name.eq_ignore_ascii_case($name) {
+                    return Self::$variant;
+                })*
+                Self::Unknown(name.to_owned())
+            }
+            pub fn name(&self) -> &str {
+                match self {
+                    $(Self::$variant => $name,)*
+                    Self::Unknown(name) => name,
+                }
+            }
+            pub fn is_depth(&self) -> bool {
+                match self {
+                    $(Self::$variant => false $(|| $depth)?,)*
+                    Self::Unknown(_) => false,
+                }
+            }
+        }
+    };
+}
+texture_formats! {
+    R8 => "R8",
+    R16F => "R16F",
+    R32F => "R32F",
+    R32UI => "R32UI",
+    RG8 => "RG8",
+    RG16F => "RG16F",
+    RG32F => "RG32F",
+    RGBA8 => "RGBA8",
+    RGBA16F => "RGBA16F",
+    RGBA32F => "RGBA32F",
+    RGBA32UI => "RGBA32UI",
+    RG11B10F => "RG11B10F",
+    RGB10A2 => "RGB10A2",
+    D16 => "D16" depth = true,
+    D24S8 => "D24S8" depth = true,
+    D32F => "D32F" depth = true,
+}
+impl Display for TextureFormat {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum Filter {
+    Point,
+    Bilinear,
+}
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub enum Wrap {
+    Clamp,
+    Repeat,
+}
+/// Decoded [`SamplerDefinition::sampler_state`]. Bit 0 selects the filter and bit 1
+/// the wrap mode, the meaning of the rest is unknown
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+pub struct SamplerState {
+    pub filter: Filter,
+    pub wrap: Wrap,
+    /// Remaining bits, kept so the state writes back unchanged
+    pub other_bits: u8,
+}
+impl SamplerState {
+    const FILTER_BIT: u8 = 1;
+    const WRAP_BIT: u8 = 1 << 1;
+    pub fn new(filter: Filter, wrap: Wrap) -> Self {
+        Self {
+            filter,
+            wrap,
+            other_bits: 0,
+        }
+    }
+}
+impl From<u8> for SamplerState {
+    fn from(value: u8) -> Self {
+        Self {
+            filter: if value & Self::FILTER_BIT != 0 {
+                Filter::Bilinear
+            } else {
+                Filter::Point
+            },
+            wrap: if value & Self::WRAP_BIT != 0 {
+                Wrap::Repeat
+            } else {
+                Wrap::Clamp
+            },
+            other_bits: value & !(Self::FILTER_BIT | Self::WRAP_BIT),
+        }
+    }
+}
+impl From<SamplerState> for u8 {
+    fn from(value: SamplerState) -> Self {
+        let mut bits = value.other_bits & !(SamplerState::FILTER_BIT | SamplerState::WRAP_BIT);
+        if value.filter == Filter::Bilinear {
+            bits |= SamplerState::FILTER_BIT;
+        }
+        if value.wrap == Wrap::Repeat {
+            bits |= SamplerState::WRAP_BIT;
+        }
+        bits
+    }
+}
+impl Display for SamplerState {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:?}, {:?}", self.filter, self.wrap)?;
+        if self.other_bits != 0 {
+            write!(f, " (other bits {:#04x})", self.other_bits)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Display;
+
+use crate::{CompiledMaterialDefinition, MinecraftVersion, WriteError, BUILTINS_NAME};
+
+/// Source of a uniform that is fed from `Core/Builtins` instead of the material's own
+/// property field, keyed by the uniform name in [`CompiledMaterialDefinition::uniform_overrides`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UniformOverride {
+    /// Name of the property field in `Core/Builtins` providing the value
+    pub source: String,
+}
+impl UniformOverride {
+    pub fn new(source: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+        }
+    }
+}
+impl From<String> for UniformOverride {
+    fn from(source: String) -> Self {
+        Self { source }
+    }
+}
+impl From<&str> for UniformOverride {
+    fn from(source: &str) -> Self {
+        Self::new(source)
+    }
+}
+
+/// Override that couldn't be kept when converting to a version without overrides
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LostOverride {
+    pub material: String,
+    pub uniform: String,
+    pub source: String,
+}
+impl Display for LostOverride {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{}: uniform {} overridden by {} was dropped",
+            self.material, self.uniform, self.source
+        )
+    }
+}
+
+impl CompiledMaterialDefinition {
+    /// Prepare the uniform overrides for writing as `version`. Before 1.21.110 they
+    /// can't be stored, so each one is inlined by copying the source property field
+    /// of `builtins` under the uniform name. Overrides without a source in `builtins`
+    /// are removed and returned.
+    pub fn inline_uniform_overrides(
+        &mut self,
+        version: MinecraftVersion,
+        builtins: Option<&CompiledMaterialDefinition>,
+    ) -> Vec<LostOverride> {
+        if version >= MinecraftVersion::V1_21_110 || self.name == BUILTINS_NAME {
+            return Vec::new();
+        }
+        let Some(overrides) = self.uniform_overrides.take() else {
+            return Vec::new();
+        };
+        let mut lost = Vec::new();
+        for (uniform, uniform_override) in overrides {
+            match builtins.and_then(|b| b.property_fields.get(&uniform_override.source)) {
+                Some(field) => {
+                    self.property_fields.insert(uniform, field.clone());
+                }
+                None => lost.push(LostOverride {
+                    material: self.name.clone(),
+                    uniform,
+                    source: uniform_override.source,
+                }),
+            }
+        }
+        lost
+    }
+    /// [`Self::inline_uniform_overrides`] for converting a material on its own, failing
+    /// with every override that couldn't be kept instead of dropping them
+    pub fn try_inline_uniform_overrides(
+        &mut self,
+        version: MinecraftVersion,
+        builtins: Option<&CompiledMaterialDefinition>,
+    ) -> Result<(), WriteError> {
+        let lost = self.inline_uniform_overrides(version, builtins);
+        if lost.is_empty() {
+            return Ok(());
+        }
+        Err(WriteError::Compat(lost_overrides_message(&lost)))
+    }
+}
+/// Every override in `lost` on one line, as conversions report them
+pub(crate) fn lost_overrides_message(lost: &[LostOverride]) -> String {
+    let lost: Vec<String> = lost.iter().map(ToString::to_string).collect();
+    lost.join(", ")
+}
@@ -0,0 +1,257 @@
+use indexmap::IndexMap;
+use scroll::Pread;
+use serde_json::{json, Map, Value};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    pass::{Pass, ShaderCode, Variant},
+    property_field::PropertyValue,
+    sampler_definition::SamplerDefinition,
+    uniform_override::lost_overrides_message,
+    CompiledMaterialDefinition, MinecraftVersion, ALL_VERSIONS,
+};
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[allow(non_camel_case_types)]
+pub enum Version {
+    V1_18_30,
+    V1_19_60,
+    V1_20_80,
+    V1_21_20,
+    V1_21_110,
+    V26_0_24,
+    V26_10_20,
+}
+macro_rules! version_map {
+    ($($variant:ident),* $(,)?) => {
+        impl From<Version> for MinecraftVersion {
+            fn from(value: Version) -> Self {
+                match value {
+                    $(Version::$variant => MinecraftVersion::$variant,)*
+                }
+            }
+        }
+        impl From<MinecraftVersion> for Version {
+            fn from(value: MinecraftVersion) -> Self {
+                match value {
+                    $(MinecraftVersion::$variant => Version::$variant,)*
+                }
+            }
+        }
+    };
+}
+version_map!(V1_18_30, V1_19_60, V1_20_80, V1_21_20, V1_21_110, V26_0_24, V26_10_20);
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = console)]
+    fn warn(message: &str);
+}
+/// Native builds of the bindings only run tests, which have no console
+#[cfg(not(target_arch = "wasm32"))]
+fn warn(message: &str) {
+    eprintln!("{message}");
+}
+fn js_error(error: impl std::fmt::Display) -> JsError {
+    JsError::new(&error.to_string())
+}
+fn parse(
+    data: &[u8],
+    version: Option<Version>,
+) -> Result<(CompiledMaterialDefinition, MinecraftVersion), JsError> {
+    if let Some(version) = version {
+        let version = version.into();
+        let material = data.pread_with(0, version).map_err(js_error)?;
+        return Ok((material, version));
+    }
+    let mut last_error = None;
+    for version in ALL_VERSIONS.into_iter().rev() {
+        match data.pread_with(0, version) {
+            Ok(material) => return Ok((material, version)),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(js_error(last_error.map_or_else(
+        || "No version could parse the material".to_string(),
+        |e| e.to_string(),
+    )))
+}
+fn write(
+    material: &CompiledMaterialDefinition,
+    version: MinecraftVersion,
+) -> Result<Vec<u8>, JsError> {
+    material.write_vec(version).map_err(js_error)
+}
+
+/// A parsed material
+#[wasm_bindgen]
+pub struct Material {
+    definition: CompiledMaterialDefinition,
+    version: MinecraftVersion,
+}
+#[wasm_bindgen]
+impl Material {
+    /// Parse a material, trying every known version when `version` is undefined
+    pub fn parse(data: &[u8], version: Option<Version>) -> Result<Material, JsError> {
+        let (definition, version) = parse(data, version)?;
+        Ok(Self {
+            definition,
+            version,
+        })
+    }
+    /// Serialize the material, as the version it was parsed with when `version` is undefined
+    pub fn write(&self, version: Option<Version>) -> Result<Vec<u8>, JsError> {
+        write(&self.definition, version.map_or(self.version, Into::into))
+    }
+    /// Version the material was parsed with
+    #[wasm_bindgen(getter)]
+    pub fn version(&self) -> Version {
+        self.version.into()
+    }
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.definition.name.clone()
+    }
+    /// JSON view of the material, shader blobs are only described by size and hash
+    #[wasm_bindgen(js_name = toJsonString)]
+    pub fn to_json_string(&self) -> String {
+        json_view(&self.definition, self.version).to_string()
+    }
+}
+
+/// Version of the newest format that can parse `data`
+#[wasm_bindgen(js_name = detectVersion)]
+pub fn detect_version(data: &[u8]) -> Option<Version> {
+    parse(data, None).ok().map(|(_, version)| version.into())
+}
+/// Convert a material to `version`, returning the converted bytes. Uniform overrides
+/// `version` can't store are dropped with a console warning
+#[wasm_bindgen]
+pub fn convert(data: &[u8], version: Version) -> Result<Vec<u8>, JsError> {
+    let (mut material, _) = parse(data, None)?;
+    let lost = material.inline_uniform_overrides(version.into(), None);
+    if !lost.is_empty() {
+        warn(&lost_overrides_message(&lost));
+    }
+    write(&material, version.into())
+}
+
+fn string_map(map: &IndexMap<String, String>) -> Value {
+    Value::Object(
+        map.iter()
+            .map(|(k, v)| (k.clone(), Value::String(v.clone())))
+            .collect(),
+    )
+}
+fn json_view(material: &CompiledMaterialDefinition, version: MinecraftVersion) -> Value {
+    let samplers: Map<String, Value> = material
+        .sampler_definitions
+        .iter()
+        .map(|(name, sampler)| (name.clone(), sampler_view(sampler)))
+        .collect();
+    let properties: Map<String, Value> = material
+        .property_fields
+        .iter()
+        .map(|(name, field)| {
+            let view = json!({
+                "type": format!("{:?}", field.field_type),
+                "num": field.num,
+                "value": match field.value() {
+                    Some(PropertyValue::Vec4(v)) => json!(v),
+                    Some(PropertyValue::Mat3(m)) => json!(m),
+                    Some(PropertyValue::Mat4(m)) => json!(m),
+                    Some(PropertyValue::External) | None => Value::Null,
+                },
+            });
+            (name.clone(), view)
+        })
+        .collect();
+    let passes: Map<String, Value> = material
+        .passes
+        .iter()
+        .map(|(name, pass)| (name.clone(), pass_view(pass)))
+        .collect();
+    json!({
+        "version": version.to_string(),
+        "encryption": format!("{:?}", material.encryption_variant),
+        "name": material.name,
+        "parent": material.parent_name,
+        "samplers": samplers,
+        "properties": properties,
+        "uniformOverrides": material.uniform_overrides.as_ref().map(|overrides| {
+            overrides
+                .iter()
+                .map(|(name, o)| (name.clone(), Value::String(o.source.clone())))
+                .collect::<Map<String, Value>>()
+        }),
+        "passes": passes,
+    })
+}
+fn sampler_view(sampler: &SamplerDefinition) -> Value {
+    json!({
+        "reg": sampler.reg,
+        "access": format!("{:?}", sampler.access),
+        "precision": format!("{:?}", sampler.precision),
+        "allowUnorderedAccess": sampler.allow_unordered_access != 0,
+        "type": sampler.sampler_type.to_string(),
+        "textureFormat": sampler.texture_format,
+        "samplerState": sampler.sampler_state,
+        "filter": sampler.state().map(|state| format!("{:?}", state.filter)),
+        "wrap": sampler.state().map(|state| format!("{:?}", state.wrap)),
+        "defaultTexture": sampler.default_texture,
+        "customTypeInfo": sampler.custom_type_info.as_ref().map(|info| json!({
+            "name": info.name,
+            "size": info.size,
+        })),
+    })
+}
+fn pass_view(pass: &Pass) -> Value {
+    json!({
+        "bitset": pass.bitset,
+        "fallback": pass.fallback,
+        "blendMode": pass.default_blendmode.as_ref().map(ToString::to_string),
+        "defaultFlags": string_map(&pass.default_flag_values),
+        "framebufferBinding": pass.framebuffer_binding,
+        "renderState": pass.render_state().to_string(),
+        "variants": pass.variants.iter().map(variant_view).collect::<Vec<_>>(),
+    })
+}
+fn variant_view(variant: &Variant) -> Value {
+    let shader_codes: Vec<Value> = variant
+        .shader_codes
+        .iter()
+        .map(|(stage, code)| {
+            json!({
+                "platform": stage.platform.to_string(),
+                "stage": stage.stage.to_string(),
+                "inputs": shader_inputs_view(code),
+                "sourceHash": code.source_hash.to_string(),
+                "size": code.bgfx_shader_data.len(),
+            })
+        })
+        .collect();
+    json!({
+        "supported": variant.is_supported,
+        "flags": string_map(&variant.flags),
+        "shaderCodes": shader_codes,
+    })
+}
+fn shader_inputs_view(code: &ShaderCode) -> Value {
+    code.shader_inputs
+        .iter()
+        .map(|(name, input)| {
+            let view = json!({
+                "type": format!("{:?}", input.input_type),
+                "attribute": format!("{:?}", input.attribute),
+                "glslName": input.attribute.glsl_name(),
+                "perInstance": input.is_per_instance,
+                "precision": input.precision_constraint.as_ref().map(|p| format!("{p:?}")),
+                "interpolation": input.interpolation_constraint.as_ref().map(|i| format!("{i:?}")),
+            });
+            (name.clone(), view)
+        })
+        .collect::<Map<String, Value>>()
+        .into()
+}
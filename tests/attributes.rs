@@ -0,0 +1,88 @@
+use materialbin::{
+    builder::{MaterialBuilder, PassBuilder, VariantBuilder},
+    pass::{Attribute, ShaderCode, ShaderCodePlatform, ShaderInput, ShaderInputType, ShaderStage},
+    CompiledMaterialDefinition, MinecraftVersion,
+};
+use scroll::Pread;
+
+#[test]
+fn unknown_attribute_roundtrips() {
+    let attribute: Attribute = [8u8, 0].pread(0).unwrap();
+    assert_eq!(attribute, Attribute::Unknown(8, 0));
+    assert_eq!(attribute.to_tuple(), (8, 0));
+    assert_eq!(Attribute::from_tuple(9, 3), Attribute::Unknown(9, 3));
+    assert_eq!(attribute.bgfx_name(), None);
+    assert_eq!(attribute.glsl_name(), None);
+
+    let mut code = ShaderCode {
+        shader_inputs: Default::default(),
+        source_hash: 0,
+        bgfx_shader_data: vec![0; 4],
+    };
+    code.shader_inputs.insert(
+        "a_unknown".to_string(),
+        ShaderInput {
+            input_type: ShaderInputType::Vec4,
+            attribute,
+            is_per_instance: false,
+            precision_constraint: None,
+            interpolation_constraint: None,
+        },
+    );
+    let version = MinecraftVersion::V1_21_20;
+    let material = MaterialBuilder::new("RenderChunk")
+        .version(version)
+        .pass(
+            "Opaque",
+            PassBuilder::new().variant(VariantBuilder::new().shader_code(
+                ShaderStage::Vertex,
+                ShaderCodePlatform::Essl310,
+                code,
+            )),
+        )
+        .build()
+        .unwrap();
+    let mut data = Vec::new();
+    material.write(&mut data, version).unwrap();
+    let parsed: CompiledMaterialDefinition = data.pread_with(0, version).unwrap();
+    assert_eq!(parsed, material);
+    let (_, code) = parsed.passes["Opaque"].variants[0]
+        .shader_codes
+        .first()
+        .unwrap();
+    assert_eq!(
+        code.shader_inputs["a_unknown"].attribute,
+        Attribute::Unknown(8, 0)
+    );
+}
+
+#[test]
+fn known_attribute_names() {
+    for (attribute, tuple, bgfx, glsl) in [
+        (
+            Attribute::Position,
+            (0, 0),
+            Some("Position"),
+            Some("a_position"),
+        ),
+        (
+            Attribute::Indices,
+            (5, 0),
+            Some("Indices"),
+            Some("a_indices"),
+        ),
+        (
+            Attribute::TexCoord7,
+            (7, 7),
+            Some("TexCoord7"),
+            Some("a_texcoord7"),
+        ),
+        (Attribute::TexCoord8, (7, 8), None, Some("a_texcoord8")),
+        (Attribute::FrontFacing, (9, 0), None, None),
+    ] {
+        assert_eq!(attribute.to_tuple(), tuple);
+        assert_eq!(Attribute::from_tuple(tuple.0, tuple.1), attribute);
+        assert_eq!(attribute.bgfx_name(), bgfx, "{attribute:?}");
+        assert_eq!(attribute.glsl_name(), glsl, "{attribute:?}");
+    }
+}
@@ -0,0 +1,180 @@
+/* Parses the material given as first argument, writes it back with the version it
+ * was parsed with and checks that the result parses to the same material.
+ * The second argument is a material older than 1.21.20, whose ESSL_310 shader
+ * is patched into the file named by the third. The fourth has uniform overrides */
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "materialbin.h"
+
+static int check(int code, const char *what) {
+    if (code < 0) {
+        fprintf(stderr, "%s failed (%d): %s\n", what, code, materialbin_last_error());
+        exit(1);
+    }
+    return code;
+}
+
+static uint8_t *read_file(const char *path, size_t *len) {
+    FILE *file = fopen(path, "rb");
+    if (!file) {
+        perror(path);
+        exit(1);
+    }
+    fseek(file, 0, SEEK_END);
+    *len = (size_t)ftell(file);
+    fseek(file, 0, SEEK_SET);
+    uint8_t *data = malloc(*len);
+    if (fread(data, 1, *len, file) != *len) {
+        perror(path);
+        exit(1);
+    }
+    fclose(file);
+    return data;
+}
+
+/* Platform numbers don't follow the IDs the material's version stores */
+static void patch_legacy(const char *path, const char *out_path) {
+    size_t len;
+    uint8_t *data = read_file(path, &len);
+    static const char source[] = "void main() { gl_FragColor = vec4(0.0); }";
+    MaterialbinPatchRule rule = {
+        .pass = NULL,
+        .stage = MATERIALBIN_STAGE_FRAGMENT,
+        .platform = MATERIALBIN_PLATFORM_ESSL_310,
+        .source = (const uint8_t *)source,
+        .source_len = sizeof(source) - 1,
+    };
+    Buffer patched;
+    if (check(patch_material(len, data, &rule, 1, &patched), "legacy patch") != 1) {
+        fprintf(stderr, "expected exactly one patched legacy shader code\n");
+        exit(1);
+    }
+    FILE *file = fopen(out_path, "wb");
+    if (!file || fwrite(patched.data, 1, patched.len, file) != patched.len) {
+        perror(out_path);
+        exit(1);
+    }
+    fclose(file);
+    free_buf(patched);
+    free(data);
+}
+
+/* Converting to 1.21.20 drops the overrides it can't store */
+static void update_overrides(const char *path) {
+    size_t len;
+    uint8_t *data = read_file(path, &len);
+    Buffer updated;
+    int code = update_file_ex(len, data, MATERIALBIN_VERSION_1_21_20, &updated, NULL);
+    if (code != MATERIALBIN_LOST_OVERRIDES || !strstr(materialbin_last_error(), "u_FogColor")) {
+        fprintf(stderr, "update_file_ex with overrides returned %d: %s\n", code,
+                materialbin_last_error());
+        exit(1);
+    }
+    free_buf(updated);
+    code = update_file(len, data, &updated);
+    if (code != MATERIALBIN_OK) {
+        fprintf(stderr, "update_file with overrides returned %d\n", code);
+        exit(1);
+    }
+    free_buf(updated);
+    free(data);
+}
+
+int main(int argc, char **argv) {
+    if (argc != 5) {
+        fprintf(stderr,
+                "usage: %s <material.bin> <legacy.material.bin> <patched.material.bin> "
+                "<overrides.material.bin>\n",
+                argv[0]);
+        return 1;
+    }
+    patch_legacy(argv[2], argv[3]);
+    update_overrides(argv[4]);
+    size_t len;
+    uint8_t *data = read_file(argv[1], &len);
+
+    MaterialbinMaterial *material = NULL;
+    check(materialbin_parse(len, data, MATERIALBIN_VERSION_AUTO, &material), "parse");
+    int version = check(materialbin_version(material), "version");
+
+    Buffer written;
+    check(materialbin_write(material, version, &written), "write");
+    if (written.len != len || memcmp(written.data, data, len) != 0) {
+        fprintf(stderr, "written material differs from the input\n");
+        return 1;
+    }
+
+    Buffer updated;
+    int detected = MATERIALBIN_VERSION_AUTO;
+    check(update_file_ex(len, data, MATERIALBIN_VERSION_AUTO, &updated, &detected), "update");
+    if (detected != version || updated.len != len) {
+        fprintf(stderr, "update_file_ex detected %d, expected %d\n", detected, version);
+        return 1;
+    }
+    free_buf(updated);
+
+    Buffer downgraded = {0};
+    int code = update_file_ex(len, data, MATERIALBIN_VERSION_1_18_30, &downgraded, NULL);
+    if (code != MATERIALBIN_ERR_WRITE || !strstr(materialbin_last_error(), "ESSL_100")) {
+        fprintf(stderr, "update_file_ex to 1.18.30 returned %d: %s\n", code,
+                materialbin_last_error());
+        return 1;
+    }
+
+    static const char source[] = "void main() { gl_FragColor = vec4(1.0); }";
+    MaterialbinPatchRule rule = {
+        .pass = "Opaque",
+        .stage = MATERIALBIN_STAGE_FRAGMENT,
+        .platform = MATERIALBIN_PLATFORM_ESSL_310,
+        .source = (const uint8_t *)source,
+        .source_len = sizeof(source) - 1,
+    };
+    Buffer patched;
+    if (check(patch_material(len, data, &rule, 1, &patched), "patch") != 1) {
+        fprintf(stderr, "expected exactly one patched shader code\n");
+        return 1;
+    }
+    if (patched.len != len + sizeof(source) - sizeof("void main() {}")) {
+        fprintf(stderr, "patched material has the wrong size\n");
+        return 1;
+    }
+    free_buf(patched);
+    rule.pass = "Missing";
+    if (patch_material(len, data, &rule, 1, &patched) != MATERIALBIN_ERR_NOT_FOUND) {
+        fprintf(stderr, "rule for a missing pass did not fail\n");
+        return 1;
+    }
+
+    MaterialbinMaterial *reparsed = NULL;
+    check(materialbin_parse(written.len, written.data, version, &reparsed), "reparse");
+    Buffer name;
+    check(materialbin_name(reparsed, &name), "name");
+    int passes = check(materialbin_pass_count(reparsed), "pass count");
+    printf("%.*s: %d passes\n", (int)name.len, (const char *)name.data, passes);
+    for (int i = 0; i < passes; i++) {
+        Buffer pass;
+        check(materialbin_pass_name(reparsed, i, &pass), "pass name");
+        int variants = check(materialbin_variant_count(reparsed, i), "variant count");
+        printf("  %.*s: %d variants\n", (int)pass.len, (const char *)pass.data, variants);
+        free_buf(pass);
+    }
+    MaterialbinMaterial *truncated = NULL;
+    if (materialbin_parse(3, data, version, &truncated) != MATERIALBIN_ERR_PARSE) {
+        fprintf(stderr, "truncated material did not fail to parse\n");
+        return 1;
+    }
+
+    if (materialbin_parse(len, NULL, version, &truncated) != MATERIALBIN_ERR_INVALID_ARGUMENT) {
+        fprintf(stderr, "null input was not rejected\n");
+        return 1;
+    }
+
+    free_buf(name);
+    free_buf(written);
+    materialbin_free(reparsed);
+    materialbin_free(material);
+    free(data);
+    return 0;
+}
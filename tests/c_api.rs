@@ -0,0 +1,189 @@
+//! Compiles `tests/c/roundtrip.c` against `include/materialbin.h` and the staticlib
+#![cfg(feature = "ffi")]
+
+use std::{env, fs, path::PathBuf, process::Command};
+
+use materialbin::{
+    bgfx_shader::BgfxShader,
+    builder::{MaterialBuilder, PassBuilder, SamplerBuilder, VariantBuilder},
+    pass::{ShaderCode, ShaderCodePlatform, ShaderStage},
+    CompiledMaterialDefinition, MinecraftVersion, ALL_VERSIONS,
+};
+use scroll::Pread;
+
+/// `cargo test` only builds the rlib, so build the staticlib next to it
+fn build_staticlib() -> PathBuf {
+    let mut cargo = Command::new(env!("CARGO"));
+    cargo
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["build", "--lib", "--features", "ffi"]);
+    if !cfg!(debug_assertions) {
+        cargo.arg("--release");
+    }
+    assert!(
+        cargo.status().unwrap().success(),
+        "building the staticlib failed"
+    );
+    // The test binary lives in <target>/<profile>/deps
+    let exe = env::current_exe().unwrap();
+    exe.parent()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .join("libmaterialbin.a")
+}
+
+#[test]
+fn header_declares_every_export() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let header = fs::read_to_string(root.join("include/materialbin.h")).unwrap();
+    let source = fs::read_to_string(root.join("src/cffi.rs")).unwrap();
+    let exports = source
+        .split("extern \"C\" fn ")
+        .skip(1)
+        .map(|rest| rest.split('(').next().unwrap());
+    for export in exports {
+        assert!(
+            header.contains(&format!(" {export}(")) || header.contains(&format!("*{export}(")),
+            "{export} is missing from materialbin.h"
+        );
+    }
+}
+
+#[test]
+fn header_versions_match() {
+    let header = include_str!("../include/materialbin.h");
+    for (code, version) in ALL_VERSIONS.iter().enumerate() {
+        let name = version.to_string().replace('.', "_");
+        assert!(
+            header.contains(&format!("MATERIALBIN_VERSION_{name} = {code},")),
+            "{version} should have version code {code} in materialbin.h"
+        );
+    }
+}
+
+#[test]
+fn c_roundtrip() {
+    let shader = BgfxShader {
+        magic: u32::from_le_bytes(*b"FSH\x0b"),
+        hash: 0,
+        uniforms: Vec::new(),
+        code: b"void main() {}".to_vec(),
+        attributes: None,
+        size: None,
+    };
+    let mut bgfx_shader_data = Vec::new();
+    shader.write(&mut bgfx_shader_data).unwrap();
+    let code = ShaderCode {
+        shader_inputs: Default::default(),
+        source_hash: 0,
+        bgfx_shader_data,
+    };
+    let material = MaterialBuilder::new("RenderChunk")
+        .version(MinecraftVersion::V1_21_20)
+        .parent("Core/Builtins")
+        .sampler("s_MatTexture", SamplerBuilder::new(0))
+        .pass(
+            "Opaque",
+            PassBuilder::new()
+                .default_flag("Fancy", "On")
+                .variant(VariantBuilder::new().flag("Fancy", "On").shader_code(
+                    ShaderStage::Fragment,
+                    ShaderCodePlatform::Essl310,
+                    code.clone(),
+                ))
+                // ESSL_100 has no ID before 1.21.20, so downgrading has to fail
+                .variant(VariantBuilder::new().flag("Fancy", "Off").shader_code(
+                    ShaderStage::Fragment,
+                    ShaderCodePlatform::Essl100,
+                    code.clone(),
+                )),
+        )
+        .build()
+        .unwrap();
+    let mut data = Vec::new();
+    material
+        .write(&mut data, MinecraftVersion::V1_21_20)
+        .unwrap();
+    // ESSL_310 has ID 9 and Metal ID 10 before 1.21.20. Without samplers
+    // the layout is the one of 1.21.20 and detection would pick that
+    let legacy_version = MinecraftVersion::V1_20_80;
+    let legacy = MaterialBuilder::new("RenderChunk")
+        .version(legacy_version)
+        .sampler("s_MatTexture", SamplerBuilder::new(0))
+        .pass(
+            "Opaque",
+            PassBuilder::new().variant(
+                VariantBuilder::new()
+                    .shader_code(
+                        ShaderStage::Fragment,
+                        ShaderCodePlatform::Essl310,
+                        code.clone(),
+                    )
+                    .shader_code(ShaderStage::Fragment, ShaderCodePlatform::Metal, code),
+            ),
+        )
+        .build()
+        .unwrap();
+    let mut legacy_data = Vec::new();
+    legacy.write(&mut legacy_data, legacy_version).unwrap();
+    let overrides = MaterialBuilder::new("RenderChunk")
+        .version(MinecraftVersion::V1_21_110)
+        .uniform_override("u_FogColor", "FogColor")
+        .build()
+        .unwrap();
+    let mut overrides_data = Vec::new();
+    overrides
+        .write(&mut overrides_data, MinecraftVersion::V1_21_110)
+        .unwrap();
+
+    let out_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("c_api");
+    fs::create_dir_all(&out_dir).unwrap();
+    let material_path = out_dir.join("RenderChunk.material.bin");
+    fs::write(&material_path, data).unwrap();
+    let legacy_path = out_dir.join("Legacy.material.bin");
+    fs::write(&legacy_path, legacy_data).unwrap();
+    let patched_path = out_dir.join("Patched.material.bin");
+    let overrides_path = out_dir.join("Overrides.material.bin");
+    fs::write(&overrides_path, overrides_data).unwrap();
+
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let program = out_dir.join("roundtrip");
+    let status = Command::new(env::var("CC").unwrap_or_else(|_| "cc".to_string()))
+        .arg(root.join("tests/c/roundtrip.c"))
+        .arg("-I")
+        .arg(root.join("include"))
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg(build_staticlib())
+        .args(["-lpthread", "-ldl", "-lm", "-o"])
+        .arg(&program)
+        .status()
+        .expect("a C compiler is needed to run this test");
+    assert!(status.success(), "compiling roundtrip.c failed");
+
+    let output = Command::new(&program)
+        .args([&material_path, &legacy_path, &patched_path, &overrides_path])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "roundtrip.c failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("RenderChunk: 1 passes"), "{stdout}");
+
+    let patched: CompiledMaterialDefinition = fs::read(&patched_path)
+        .unwrap()
+        .pread_with(0, legacy_version)
+        .unwrap();
+    for (stage, code) in &patched.passes["Opaque"].variants[0].shader_codes {
+        let shader: BgfxShader = code.bgfx_shader_data.pread(0).unwrap();
+        let expected: &[u8] = match stage.platform {
+            ShaderCodePlatform::Essl310 => b"void main() { gl_FragColor = vec4(0.0); }",
+            _ => b"void main() {}",
+        };
+        assert_eq!(shader.code, expected, "{}", stage.platform);
+    }
+}
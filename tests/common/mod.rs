@@ -0,0 +1,11 @@
+//! Fixtures shared by the integration tests
+use materialbin::pass::ShaderCode;
+
+/// Shader code whose bgfx data is four `data` bytes, telling versions apart by `data`
+pub fn code(data: u8) -> ShaderCode {
+    ShaderCode {
+        shader_inputs: Default::default(),
+        source_hash: 0,
+        bgfx_shader_data: vec![data; 4],
+    }
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use common::code;
+use materialbin::{
+    builder::{MaterialBuilder, PassBuilder, SamplerBuilder, VariantBuilder},
+    diff::{flag_set_key, variant_keys, FieldChange},
+    pass::{ShaderCodePlatform, ShaderStage, Variant},
+    CompiledMaterialDefinition, IndexMap, MinecraftVersion,
+};
+
+fn material() -> CompiledMaterialDefinition {
+    MaterialBuilder::new("RenderChunk")
+        .version(MinecraftVersion::V1_21_20)
+        .sampler("s_MatTexture", SamplerBuilder::new(0))
+        .pass(
+            "Opaque",
+            PassBuilder::new()
+                .default_flag("Fancy", "On")
+                .default_flag("Fog", "Off")
+                .variant(
+                    VariantBuilder::new()
+                        .flag("Fancy", "On")
+                        .flag("Fog", "Off")
+                        .shader_code(ShaderStage::Fragment, ShaderCodePlatform::Essl310, code(0)),
+                )
+                .variant(VariantBuilder::new().flag("Fancy", "Off")),
+        )
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn identical_materials_have_no_diff() {
+    let diff = material().diff(&material());
+    assert!(diff.is_empty());
+    assert_eq!(diff.to_string(), "Material RenderChunk\n  (no changes)\n");
+}
+
+#[test]
+fn reordering_is_not_a_change() {
+    let old = material();
+    let mut new = material();
+    new.passes["Opaque"].variants.reverse();
+    let variant = &mut new.passes["Opaque"].variants[1];
+    variant.flags.reverse();
+    assert!(old.diff(&new).is_empty());
+}
+
+#[test]
+fn reports_changes_by_name() {
+    let old = material();
+    let mut new = material();
+    new.parent_name = Some("Core/Builtins".to_string());
+    new.sampler_definitions.clear();
+    let pass = &mut new.passes["Opaque"];
+    pass.fallback = "Transparent".to_string();
+    pass.variants[0].shader_codes[0] = code(1);
+    pass.variants.pop();
+
+    let diff = old.diff(&new);
+    assert_eq!(
+        diff.fields,
+        [FieldChange {
+            field: "parent_name",
+            old: "None".to_string(),
+            new: "Some(\"Core/Builtins\")".to_string(),
+        }]
+    );
+    assert_eq!(diff.sampler_definitions.removed, ["s_MatTexture"]);
+    let (name, pass) = &diff.passes.changed[0];
+    assert_eq!(name, "Opaque");
+    assert_eq!(pass.fields[0].field, "fallback");
+    assert_eq!(pass.variants.removed, ["Fancy=Off"]);
+    let (key, variant) = &pass.variants.changed[0];
+    assert_eq!(key, "Fancy=On, Fog=Off");
+    let (stage, changes) = &variant.shader_codes.changed[0];
+    assert_eq!(stage, "ESSL_310 Fragment");
+    assert_eq!(changes[0].field, "bgfx_shader_data");
+
+    let text = diff.to_string();
+    for line in [
+        "Material RenderChunk",
+        "  parent_name: None -> Some(\"Core/Builtins\")",
+        "  Samplers:\n    - s_MatTexture",
+        "  Passes:\n    ~ Opaque",
+        "      Variants:\n        - Fancy=Off\n        ~ Fancy=On, Fog=Off",
+        "          Shader codes:\n            ~ ESSL_310 Fragment",
+    ] {
+        assert!(text.contains(line), "{line:?} missing from\n{text}");
+    }
+}
+
+#[test]
+fn duplicate_flag_sets_are_kept_apart() {
+    let variant = |data| {
+        let mut variant = Variant {
+            is_supported: true,
+            flags: IndexMap::default(),
+            shader_codes: IndexMap::default(),
+        };
+        variant.flags.insert("Fancy".to_string(), "On".to_string());
+        let stage = material().passes["Opaque"].variants[0]
+            .shader_codes
+            .keys()
+            .next()
+            .unwrap()
+            .clone();
+        variant.shader_codes.insert(stage, code(data));
+        variant
+    };
+    let variants = [variant(0), variant(1)];
+    let keys: Vec<_> = variant_keys(&variants).into_keys().collect();
+    assert_eq!(keys, ["Fancy=On", "Fancy=On #2"]);
+    assert_eq!(flag_set_key(&variants[1].flags), "Fancy=On");
+
+    let mut new = material();
+    let pass = &mut new.passes["Opaque"];
+    pass.variants.clear();
+    pass.variants.extend(variants);
+    let mut duplicated = new.clone();
+    duplicated.passes["Opaque"].variants[1].shader_codes[0] = code(2);
+    let diff = new.diff(&duplicated);
+    let (_, pass) = &diff.passes.changed[0];
+    assert_eq!(pass.variants.changed.len(), 1);
+    assert_eq!(pass.variants.changed[0].0, "Fancy=On #2");
+}
@@ -0,0 +1,71 @@
+use materialbin::{
+    builder::{MaterialBuilder, PassBuilder, VariantBuilder},
+    pass::{BlendMode, ShaderCode, ShaderCodePlatform, ShaderStage},
+    CompiledMaterialDefinition, MinecraftVersion, ParseOptions,
+};
+use scroll::Pread;
+
+/// Material using values no known revision has, written from hand-made variants
+fn unknown_values(version: MinecraftVersion) -> Vec<u8> {
+    let code = ShaderCode {
+        shader_inputs: Default::default(),
+        source_hash: 1,
+        bgfx_shader_data: vec![1, 2, 3],
+    };
+    let mut material = MaterialBuilder::new("RenderChunk")
+        .version(version)
+        .pass(
+            "Opaque",
+            PassBuilder::new()
+                .blend_mode(BlendMode::Unknown(99))
+                .variant(VariantBuilder::new().shader_code(
+                    ShaderStage::Fragment,
+                    ShaderCodePlatform::Essl310,
+                    code,
+                )),
+        )
+        .build()
+        .unwrap();
+    let variant = &mut material.passes["Opaque"].variants[0];
+    let (mut stage, code) = variant.shader_codes.pop().unwrap();
+    stage.stage = ShaderStage::Other(7);
+    variant.shader_codes.insert(stage, code);
+    let mut data = Vec::new();
+    material.write(&mut data, version).unwrap();
+    data
+}
+
+#[test]
+fn strict_parsing_rejects_unknown_values() {
+    let version = MinecraftVersion::V1_21_20;
+    let data = unknown_values(version);
+    assert!(data
+        .pread_with::<CompiledMaterialDefinition>(0, version)
+        .is_err());
+}
+
+#[test]
+fn lenient_parsing_writes_back_identical() {
+    let version = MinecraftVersion::V1_21_20;
+    let data = unknown_values(version);
+    let material: CompiledMaterialDefinition =
+        data.pread_with(0, ParseOptions::lenient(version)).unwrap();
+    let pass = &material.passes["Opaque"];
+    assert_eq!(pass.default_blendmode, Some(BlendMode::Unknown(99)));
+    let (stage, _) = pass.variants[0].shader_codes.first().unwrap();
+    assert_eq!(stage.stage, ShaderStage::Other(7));
+    assert_eq!(stage.stage.to_string(), "Other(7)");
+
+    let mut written = Vec::new();
+    material.write(&mut written, version).unwrap();
+    assert_eq!(written, data);
+}
+
+#[test]
+fn unknown_stage_is_not_lenient() {
+    assert_eq!(ShaderStage::Unknown.to_u8(), 3);
+    assert_eq!(ShaderStage::Unknown.to_string(), "Unknown");
+    assert_eq!("unknown".parse(), Ok(ShaderStage::Unknown));
+    let stage: ShaderStage = [3].pread_with(0, ParseOptions::default()).unwrap();
+    assert_eq!(stage, ShaderStage::Unknown);
+}
@@ -0,0 +1,220 @@
+mod common;
+
+use common::code;
+use materialbin::{
+    builder::{MaterialBuilder, PassBuilder, SamplerBuilder, VariantBuilder},
+    merge::{merge, MergeConflict},
+    pass::{PlatformShaderStage, ShaderCode, ShaderCodePlatform, ShaderStage},
+    CompiledMaterialDefinition, MinecraftVersion,
+};
+
+/// One sampler, an empty Transparent pass and an Opaque one with one variant that
+/// has a fragment shader for ESSL_310 and Metal
+fn base() -> CompiledMaterialDefinition {
+    MaterialBuilder::new("RenderChunk")
+        .version(MinecraftVersion::V1_21_20)
+        .sampler("s_MatTexture", SamplerBuilder::new(0))
+        .pass("Transparent", PassBuilder::new())
+        .pass(
+            "Opaque",
+            PassBuilder::new().variant(
+                VariantBuilder::new()
+                    .shader_code(ShaderStage::Fragment, ShaderCodePlatform::Essl310, code(0))
+                    .shader_code(ShaderStage::Fragment, ShaderCodePlatform::Metal, code(0)),
+            ),
+        )
+        .build()
+        .unwrap()
+}
+fn find(
+    material: &CompiledMaterialDefinition,
+    platform: ShaderCodePlatform,
+) -> Option<&ShaderCode> {
+    material.passes["Opaque"].variants[0]
+        .shader_codes
+        .iter()
+        .find(|(stage, _)| stage.platform == platform)
+        .map(|(_, code)| code)
+}
+fn set(material: &mut CompiledMaterialDefinition, platform: ShaderCodePlatform, data: Option<u8>) {
+    let shader_codes = &mut material.passes["Opaque"].variants[0].shader_codes;
+    let stage = shader_codes
+        .keys()
+        .find(|stage| stage.platform == platform)
+        .cloned()
+        .unwrap();
+    match data {
+        Some(data) => shader_codes[&stage] = code(data),
+        None => {
+            shader_codes.shift_remove(&stage);
+        }
+    }
+}
+fn value_conflict(conflict: &MergeConflict) -> &str {
+    match conflict {
+        MergeConflict::Value { path } => path,
+        other => panic!("expected a value conflict, got {other}"),
+    }
+}
+fn shader_conflict(
+    conflict: &MergeConflict,
+) -> (
+    &PlatformShaderStage,
+    Option<&ShaderCode>,
+    Option<&ShaderCode>,
+) {
+    match conflict {
+        MergeConflict::ShaderCode {
+            stage,
+            ours,
+            theirs,
+            ..
+        } => (stage, ours.as_deref(), theirs.as_deref()),
+        other => panic!("expected a shader code conflict, got {other}"),
+    }
+}
+
+#[test]
+fn keeps_blob_only_ours_replaced() {
+    let base = base();
+    let mut ours = base.clone();
+    set(&mut ours, ShaderCodePlatform::Essl310, Some(1));
+    let mut theirs = base.clone();
+    set(&mut theirs, ShaderCodePlatform::Metal, Some(2));
+
+    let result = merge(&base, &ours, &theirs);
+    assert!(!result.has_conflicts(), "{:?}", result.conflicts);
+    assert_eq!(
+        find(&result.material, ShaderCodePlatform::Essl310),
+        Some(&code(1))
+    );
+    assert_eq!(
+        find(&result.material, ShaderCodePlatform::Metal),
+        Some(&code(2))
+    );
+}
+
+#[test]
+fn same_shader_code_edited_on_both_sides_conflicts() {
+    let base = base();
+    let mut ours = base.clone();
+    set(&mut ours, ShaderCodePlatform::Essl310, Some(1));
+    let mut theirs = base.clone();
+    set(&mut theirs, ShaderCodePlatform::Essl310, Some(2));
+
+    let result = merge(&base, &ours, &theirs);
+    assert_eq!(result.conflicts.len(), 1, "{:?}", result.conflicts);
+    let (stage, o, t) = shader_conflict(&result.conflicts[0]);
+    assert_eq!(stage.platform, ShaderCodePlatform::Essl310);
+    assert_eq!(o, Some(&code(1)));
+    assert_eq!(t, Some(&code(2)));
+    assert_eq!(
+        find(&result.material, ShaderCodePlatform::Essl310),
+        Some(&code(2))
+    );
+
+    // The same edit on both sides is no conflict
+    let result = merge(&base, &ours, &ours);
+    assert!(!result.has_conflicts(), "{:?}", result.conflicts);
+}
+
+#[test]
+fn delete_against_edit_conflicts() {
+    let base = base();
+    let mut deleted = base.clone();
+    set(&mut deleted, ShaderCodePlatform::Essl310, None);
+    let mut edited = base.clone();
+    set(&mut edited, ShaderCodePlatform::Essl310, Some(2));
+
+    let result = merge(&base, &deleted, &edited);
+    assert_eq!(result.conflicts.len(), 1, "{:?}", result.conflicts);
+    let (_, o, t) = shader_conflict(&result.conflicts[0]);
+    assert_eq!((o, t), (None, Some(&code(2))));
+    assert_eq!(
+        find(&result.material, ShaderCodePlatform::Essl310),
+        Some(&code(2))
+    );
+
+    let result = merge(&base, &edited, &deleted);
+    assert_eq!(result.conflicts.len(), 1, "{:?}", result.conflicts);
+    let (_, o, t) = shader_conflict(&result.conflicts[0]);
+    assert_eq!((o, t), (Some(&code(2)), None));
+    assert_eq!(
+        find(&result.material, ShaderCodePlatform::Essl310),
+        Some(&code(2))
+    );
+
+    // A deletion against an untouched side goes through
+    let result = merge(&base, &deleted, &base);
+    assert!(!result.has_conflicts(), "{:?}", result.conflicts);
+    assert_eq!(find(&result.material, ShaderCodePlatform::Essl310), None);
+}
+
+#[test]
+fn pass_field_edited_on_both_sides_conflicts() {
+    let base = base();
+    let mut ours = base.clone();
+    ours.passes["Opaque"].fallback = "Ours".to_string();
+    let mut theirs = base.clone();
+    theirs.passes["Opaque"].fallback = "Theirs".to_string();
+
+    let result = merge(&base, &ours, &theirs);
+    assert_eq!(result.conflicts.len(), 1, "{:?}", result.conflicts);
+    assert_eq!(
+        value_conflict(&result.conflicts[0]),
+        "RenderChunk.passes[Opaque].fallback"
+    );
+    assert_eq!(result.material.passes["Opaque"].fallback, "Theirs");
+
+    let result = merge(&base, &ours, &base);
+    assert!(!result.has_conflicts(), "{:?}", result.conflicts);
+    assert_eq!(result.material.passes["Opaque"].fallback, "Ours");
+}
+
+#[test]
+fn pass_delete_against_edit_conflicts() {
+    let base = base();
+    let mut deleted = base.clone();
+    deleted.passes.shift_remove("Transparent");
+    let mut edited = base.clone();
+    edited.passes["Transparent"].fallback = "Opaque".to_string();
+
+    let result = merge(&base, &deleted, &edited);
+    assert_eq!(result.conflicts.len(), 1, "{:?}", result.conflicts);
+    assert_eq!(
+        value_conflict(&result.conflicts[0]),
+        "RenderChunk.passes[Transparent]"
+    );
+    assert_eq!(result.material.passes["Transparent"].fallback, "Opaque");
+
+    let result = merge(&base, &base, &deleted);
+    assert!(!result.has_conflicts(), "{:?}", result.conflicts);
+    assert!(!result.material.passes.contains_key("Transparent"));
+}
+
+#[test]
+fn sampler_conflicts_and_deletions() {
+    let base = base();
+    let mut ours = base.clone();
+    ours.sampler_definitions["s_MatTexture"].reg = 1;
+    let mut theirs = base.clone();
+    theirs.sampler_definitions["s_MatTexture"].reg = 2;
+
+    let result = merge(&base, &ours, &theirs);
+    assert_eq!(result.conflicts.len(), 1, "{:?}", result.conflicts);
+    assert_eq!(
+        value_conflict(&result.conflicts[0]),
+        "RenderChunk.sampler_definitions[s_MatTexture]"
+    );
+    assert_eq!(result.material.sampler_definitions["s_MatTexture"].reg, 2);
+
+    let mut deleted = base.clone();
+    deleted.sampler_definitions.shift_remove("s_MatTexture");
+    let result = merge(&base, &deleted, &theirs);
+    assert_eq!(result.conflicts.len(), 1, "{:?}", result.conflicts);
+    assert_eq!(result.material.sampler_definitions["s_MatTexture"].reg, 2);
+
+    let result = merge(&base, &deleted, &base);
+    assert!(!result.has_conflicts(), "{:?}", result.conflicts);
+    assert!(result.material.sampler_definitions.is_empty());
+}
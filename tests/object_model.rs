@@ -0,0 +1,65 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use materialbin::{
+    builder::{MaterialBuilder, PassBuilder, SamplerBuilder, VariantBuilder},
+    pass::BlendMode,
+    CompiledMaterialDefinition, MinecraftVersion,
+};
+
+fn material() -> CompiledMaterialDefinition {
+    MaterialBuilder::new("RenderChunk")
+        .version(MinecraftVersion::V1_21_110)
+        .sampler("s_MatTexture", SamplerBuilder::new(0))
+        .sampler("s_LightMapTexture", SamplerBuilder::new(1))
+        .uniform_override("u_FogColor", "FogColor")
+        .uniform_override("u_FogControl", "FogControl")
+        .pass(
+            "Opaque",
+            PassBuilder::new()
+                .default_flag("Fancy", "On")
+                .default_flag("Fog", "Off")
+                .variant(VariantBuilder::new().flag("Fancy", "On").flag("Fog", "Off")),
+        )
+        .pass("Transparent", PassBuilder::new())
+        .build()
+        .unwrap()
+}
+fn hash(material: &CompiledMaterialDefinition) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    material.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[test]
+fn map_order_is_not_compared() {
+    let old = material();
+    let mut new = material();
+    new.sampler_definitions.reverse();
+    new.uniform_overrides.as_mut().unwrap().reverse();
+    new.passes.reverse();
+    let pass = &mut new.passes["Opaque"];
+    pass.default_flag_values.reverse();
+    pass.variants[0].flags.reverse();
+    assert_eq!(old, new);
+    assert_eq!(hash(&old), hash(&new));
+
+    new.passes["Opaque"].variants[0].flags["Fog"] = "On".to_string();
+    assert_ne!(old, new);
+    assert_ne!(hash(&old), hash(&new));
+}
+
+#[test]
+fn duplicate_pass() {
+    let mut material = material();
+    let copy = material.duplicate_pass("Opaque", "AlphaTest").unwrap();
+    copy.default_blendmode = Some(BlendMode::AlphaBlend);
+    assert_eq!(material.passes.len(), 3);
+    let (opaque, alpha_test) = (&material.passes["Opaque"], &material.passes["AlphaTest"]);
+    assert_eq!(alpha_test.variants, opaque.variants);
+    assert_ne!(alpha_test.default_blendmode, opaque.default_blendmode);
+
+    let before = material.clone();
+    assert!(material.duplicate_pass("Opaque", "Transparent").is_none());
+    assert!(material.duplicate_pass("Missing", "Copy").is_none());
+    assert_eq!(material, before);
+}
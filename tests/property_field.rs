@@ -0,0 +1,124 @@
+use materialbin::{
+    builder::MaterialBuilder,
+    property_field::{PropertyField, PropertyType, PropertyValue},
+    CompiledMaterialDefinition, MyError, ALL_VERSIONS,
+};
+use scroll::Pread;
+
+/// Property field as stored in a material after its name, in the layout the original
+/// parser read game materials with
+fn field_bytes(field_type: u16, num: u32, data: Option<&[u8]>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend(field_type.to_le_bytes());
+    bytes.extend(num.to_le_bytes());
+    bytes.push(data.is_some().into());
+    bytes.extend(data.unwrap_or_default());
+    bytes
+}
+fn floats(values: &[f32]) -> Vec<u8> {
+    values.iter().flat_map(|f| f.to_le_bytes()).collect()
+}
+fn read(bytes: &[u8]) -> Result<(PropertyField, usize), MyError> {
+    let mut offset = 0;
+    let field = bytes.gread(&mut offset)?;
+    Ok((field, offset))
+}
+fn roundtrip(bytes: &[u8]) -> PropertyField {
+    let (field, read) = read(bytes).unwrap();
+    assert_eq!(read, bytes.len(), "{field:?} left bytes unread");
+    let mut written = Vec::new();
+    field.write(&mut written).unwrap();
+    assert_eq!(written, bytes, "{field:?} changed on write");
+    assert_eq!(field.serialized_size().unwrap(), bytes.len());
+    field
+}
+
+#[test]
+fn vec4_with_data() {
+    // FogColor
+    let data = floats(&[0.5, 0.6, 0.7, 1.0]);
+    let field = roundtrip(&field_bytes(2, 1, Some(&data)));
+    assert_eq!(
+        field.value(),
+        Some(PropertyValue::Vec4([0.5, 0.6, 0.7, 1.0]))
+    );
+}
+
+#[test]
+fn matrices() {
+    let identity3 = floats(&[1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+    let field = roundtrip(&field_bytes(3, 1, Some(&identity3)));
+    assert_eq!(
+        field.value(),
+        Some(PropertyValue::Mat3([
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0]
+        ]))
+    );
+    // Bone matrices, an array without a default
+    let field = roundtrip(&field_bytes(4, 8, None));
+    assert_eq!(field.num, 8);
+    assert_eq!(field.value(), None);
+}
+
+#[test]
+fn external() {
+    let bytes = field_bytes(5, 1, None);
+    // Same as the original parser: type, num and an unset has-data byte
+    assert_eq!(bytes, [5, 0, 1, 0, 0, 0, 0]);
+    let field = roundtrip(&bytes);
+    assert_eq!(field.field_type, PropertyType::External);
+    assert_eq!(field.value(), Some(PropertyValue::External));
+
+    // External properties have no data to follow a set has-data byte
+    let (field, read) = read(&field_bytes(5, 1, Some(&[]))).unwrap();
+    assert_eq!(read, 7);
+    assert_eq!(field.vector_data, None);
+    let mut written = Vec::new();
+    field.write(&mut written).unwrap();
+    assert_eq!(written, bytes);
+}
+
+#[test]
+fn truncated_data_is_an_error() {
+    let data = floats(&[1.0; 16]);
+    let bytes = field_bytes(4, 1, Some(&data));
+    for len in 0..bytes.len() {
+        assert!(
+            read(&bytes[..len]).is_err(),
+            "reading {len} of {} bytes should fail",
+            bytes.len()
+        );
+    }
+}
+
+#[test]
+fn wrong_data_size_is_a_write_error() {
+    let mut field = roundtrip(&field_bytes(2, 1, None));
+    field.vector_data = Some(vec![0; 12]);
+    assert!(field.write(&mut Vec::new()).is_err());
+}
+
+#[test]
+fn material_with_external_property_roundtrips() {
+    let mut external = roundtrip(&field_bytes(5, 1, None));
+    let mut fog = external.clone();
+    fog.set_value(PropertyValue::Vec4([0.1, 0.2, 0.3, 1.0]));
+    external.num = 2;
+    for version in ALL_VERSIONS {
+        let material = MaterialBuilder::new("Sky")
+            .version(version)
+            .property("u_externalTexture", external.clone())
+            .property("FogColor", fog.clone())
+            .build()
+            .unwrap();
+        let mut data = Vec::new();
+        material.write(&mut data, version).unwrap();
+        let parsed: CompiledMaterialDefinition = data.pread_with(0, version).unwrap();
+        assert_eq!(parsed, material, "{version}");
+        let mut rewritten = Vec::new();
+        parsed.write(&mut rewritten, version).unwrap();
+        assert_eq!(rewritten, data, "{version}");
+    }
+}
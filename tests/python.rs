@@ -0,0 +1,97 @@
+//! Builds the extension module and runs `tests/python/test_materialbin.py` against it
+#![cfg(feature = "python")]
+
+use std::{env, fs, path::PathBuf, process::Command};
+
+use materialbin::{
+    builder::{MaterialBuilder, PassBuilder, SamplerBuilder, VariantBuilder},
+    pass::{ShaderCode, ShaderCodePlatform, ShaderStage},
+    CompiledMaterialDefinition, MinecraftVersion,
+};
+
+/// `cargo test` only builds the rlib, so build the extension module next to it
+fn build_extension() -> PathBuf {
+    let mut cargo = Command::new(env!("CARGO"));
+    cargo.current_dir(env!("CARGO_MANIFEST_DIR")).args([
+        "build",
+        "--lib",
+        "--features",
+        "python,pyo3/extension-module",
+    ]);
+    if !cfg!(debug_assertions) {
+        cargo.arg("--release");
+    }
+    assert!(
+        cargo.status().unwrap().success(),
+        "building the extension module failed"
+    );
+    // The test binary lives in <target>/<profile>/deps
+    let exe = env::current_exe().unwrap();
+    exe.parent()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .join("libmaterialbin.so")
+}
+fn write(material: &CompiledMaterialDefinition, version: MinecraftVersion) -> Vec<u8> {
+    material.write_vec(version).unwrap()
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn python_bindings() {
+    let code = ShaderCode {
+        shader_inputs: Default::default(),
+        source_hash: 0,
+        bgfx_shader_data: vec![0; 8],
+    };
+    let material = MaterialBuilder::new("RenderChunk")
+        .version(MinecraftVersion::V1_21_20)
+        .sampler("s_MatTexture", SamplerBuilder::new(0))
+        .pass(
+            "Opaque",
+            PassBuilder::new().default_flag("Fancy", "On").variant(
+                VariantBuilder::new().flag("Fancy", "On").shader_code(
+                    ShaderStage::Fragment,
+                    ShaderCodePlatform::Essl310,
+                    code,
+                ),
+            ),
+        )
+        .build()
+        .unwrap();
+    let overrides = MaterialBuilder::new("RenderChunk")
+        .version(MinecraftVersion::V1_21_110)
+        .uniform_override("u_FogColor", "FogColor")
+        .build()
+        .unwrap();
+
+    let out_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("python");
+    fs::create_dir_all(&out_dir).unwrap();
+    fs::write(
+        out_dir.join("RenderChunk.material.bin"),
+        write(&material, MinecraftVersion::V1_21_20),
+    )
+    .unwrap();
+    fs::write(
+        out_dir.join("Overrides.material.bin"),
+        write(&overrides, MinecraftVersion::V1_21_110),
+    )
+    .unwrap();
+    fs::copy(build_extension(), out_dir.join("materialbin.so")).unwrap();
+
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let output = Command::new(env::var("PYTHON").unwrap_or_else(|_| "python3".to_string()))
+        .arg(root.join("tests/python/test_materialbin.py"))
+        .env("PYTHONPATH", &out_dir)
+        .env("MATERIALBIN_FIXTURES", &out_dir)
+        .output()
+        .expect("a Python interpreter is needed to run this test");
+    assert!(
+        output.status.success(),
+        "test_materialbin.py failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("test_update ok"), "{stdout}");
+}
@@ -0,0 +1,71 @@
+"""Tests of the Python bindings. tests/python.rs builds the module, writes the
+fixtures into the directory in MATERIALBIN_FIXTURES and runs this file, pytest
+works as well with both in place"""
+import os
+import warnings
+
+import materialbin
+from materialbin import Material, MinecraftVersion, ShaderCodePlatform
+
+
+def fixture(name):
+    with open(os.path.join(os.environ["MATERIALBIN_FIXTURES"], name), "rb") as file:
+        return file.read()
+
+
+def test_parse_modify_write():
+    data = fixture("RenderChunk.material.bin")
+    material = Material.parse(data)
+    assert material.version == MinecraftVersion.V1_21_20
+    assert material.name == "RenderChunk"
+    assert material.write() == data
+
+    (opaque,) = material.passes()
+    assert opaque.name == "Opaque"
+    (variant,) = opaque.variants()
+    assert variant.flags == {"Fancy": "On"}
+    (code,) = variant.shader_codes()
+    assert code.platform == ShaderCodePlatform.Essl310
+    assert code.stage == "Fragment"
+    code.bgfx_shader_data = b"\x01\x02"
+
+    reparsed = Material.parse(material.write(), MinecraftVersion.V1_21_20)
+    (code,) = reparsed.passes()[0].variants()[0].shader_codes()
+    assert code.bgfx_shader_data == b"\x01\x02"
+    assert len(material.write()) == len(data) - 6
+
+
+def test_update():
+    data = fixture("RenderChunk.material.bin")
+    old = materialbin.update(data, MinecraftVersion.V1_20_80)
+    material = Material.parse(old, MinecraftVersion.V1_20_80)
+    assert material.write(MinecraftVersion.V1_21_20) == data
+
+
+def test_update_drops_lost_overrides():
+    data = fixture("Overrides.material.bin")
+    with warnings.catch_warnings(record=True) as caught:
+        warnings.simplefilter("always")
+        kept = materialbin.update(data, MinecraftVersion.V1_21_110)
+        assert not caught
+        materialbin.update(data, MinecraftVersion.V1_21_20)
+    (warning,) = caught
+    assert issubclass(warning.category, UserWarning)
+    assert "u_FogColor" in str(warning.message)
+    assert kept == data
+
+
+def test_parse_errors():
+    try:
+        Material.parse(b"\x00" * 8)
+    except ValueError:
+        pass
+    else:
+        raise AssertionError("parsing garbage did not fail")
+
+
+if __name__ == "__main__":
+    for name, test in list(globals().items()):
+        if name.startswith("test_"):
+            test()
+            print(f"{name} ok")
@@ -0,0 +1,146 @@
+use materialbin::{
+    builder::{MaterialBuilder, PassBuilder},
+    pass::{BlendMode, Pass},
+    render_state::{BlendEquation, BlendFactor, FramebufferBinding, RenderState},
+    CompiledMaterialDefinition, MinecraftVersion, ParseOptions,
+};
+use scroll::Pread;
+
+const MODES: [BlendMode; 12] = [
+    BlendMode::None,
+    BlendMode::Replace,
+    BlendMode::AlphaBlend,
+    BlendMode::ColorBlendAlphaAdd,
+    BlendMode::PreMultiplied,
+    BlendMode::InvertColor,
+    BlendMode::Additive,
+    BlendMode::AdditiveAlpha,
+    BlendMode::Multiply,
+    BlendMode::MultiplyBoth,
+    BlendMode::InverseSrcAlpha,
+    BlendMode::SrcAlpha,
+];
+
+fn pass(version: MinecraftVersion, blend_mode: BlendMode) -> Pass {
+    let mut builder = PassBuilder::new().blend_mode(blend_mode);
+    if version >= MinecraftVersion::V26_0_24 {
+        builder = builder.framebuffer_binding(2);
+    }
+    builder.build(version).unwrap()
+}
+fn roundtrip(
+    material: &CompiledMaterialDefinition,
+    options: ParseOptions,
+) -> CompiledMaterialDefinition {
+    let mut data = Vec::new();
+    material.write(&mut data, options.version).unwrap();
+    data.pread_with(0, options).unwrap()
+}
+
+#[test]
+fn blend_modes_roundtrip() {
+    for (value, mode) in MODES.into_iter().enumerate() {
+        assert_eq!(usize::from(mode.to_u16()), value);
+        let parsed: BlendMode = mode.to_u16().to_le_bytes().pread(0).unwrap();
+        assert_eq!(parsed, mode);
+        assert_eq!(mode.to_string().parse(), Ok(mode));
+        assert_eq!(mode.equation().is_none(), mode == BlendMode::None, "{mode}");
+    }
+    let strict: Result<BlendMode, _> = 12u16.to_le_bytes().pread(0);
+    assert!(strict.is_err());
+    let lenient = ParseOptions::lenient(MinecraftVersion::V1_21_20);
+    let unknown: BlendMode = 12u16.to_le_bytes().pread_with(0, lenient).unwrap();
+    assert_eq!(unknown, BlendMode::Unknown(12));
+    assert_eq!(unknown.to_u16(), 12);
+    assert_eq!(unknown.equation(), None);
+}
+
+#[test]
+fn framebuffer_binding_roundtrips() {
+    for value in [0, 1, 7, u32::MAX] {
+        let binding = FramebufferBinding::from(value);
+        assert_eq!(u32::from(binding), value);
+        assert_eq!(binding == FramebufferBinding::Default, value == 0);
+    }
+
+    let version = MinecraftVersion::V26_0_24;
+    let mut material = MaterialBuilder::new("RenderChunk")
+        .version(version)
+        .pass("Opaque", PassBuilder::new())
+        .build()
+        .unwrap();
+    let pass = &mut material.passes["Opaque"];
+    assert_eq!(pass.framebuffer(), Some(FramebufferBinding::Default));
+    pass.set_framebuffer(Some(FramebufferBinding::Other(3)));
+    assert_eq!(pass.framebuffer_binding, Some(3));
+    let parsed = roundtrip(&material, version.into());
+    assert_eq!(
+        parsed.passes["Opaque"].framebuffer(),
+        Some(FramebufferBinding::Other(3))
+    );
+}
+
+#[test]
+fn render_state_roundtrips() {
+    for version in [MinecraftVersion::V1_21_20, MinecraftVersion::V26_0_24] {
+        for mode in MODES {
+            let mut material = MaterialBuilder::new("RenderChunk")
+                .version(version)
+                .build()
+                .unwrap();
+            material
+                .passes
+                .insert("Opaque".to_string(), pass(version, mode));
+            let state = material.passes["Opaque"].render_state();
+            let parsed = roundtrip(&material, version.into());
+            assert_eq!(
+                parsed.passes["Opaque"].render_state(),
+                state,
+                "{version} {mode}"
+            );
+        }
+    }
+}
+
+#[test]
+fn display() {
+    use BlendFactor::*;
+    let equation = BlendEquation {
+        src_color: SrcAlpha,
+        dst_color: InvSrcAlpha,
+        src_alpha: SrcAlpha,
+        dst_alpha: InvSrcAlpha,
+    };
+    assert_eq!(equation.to_string(), "src * SrcAlpha + dst * InvSrcAlpha");
+    assert_eq!(
+        BlendMode::ColorBlendAlphaAdd
+            .equation()
+            .unwrap()
+            .to_string(),
+        "src * SrcAlpha + dst * InvSrcAlpha, alpha src * One + dst * One"
+    );
+    assert_eq!(FramebufferBinding::Default.to_string(), "default");
+    assert_eq!(FramebufferBinding::Other(4).to_string(), "binding 4");
+
+    let state = |blend_mode: Option<BlendMode>, framebuffer| RenderState {
+        blend_mode,
+        blend: blend_mode.and_then(|mode| mode.equation()),
+        framebuffer,
+    };
+    assert_eq!(
+        state(Some(BlendMode::Additive), None).to_string(),
+        "blend Additive (src * One + dst * One)"
+    );
+    assert_eq!(
+        state(Some(BlendMode::None), Some(FramebufferBinding::Default)).to_string(),
+        "blend None (off), framebuffer default"
+    );
+    assert_eq!(
+        state(Some(BlendMode::Unknown(40)), None).to_string(),
+        "blend Unknown(40)"
+    );
+    assert_eq!(
+        state(None, Some(FramebufferBinding::Other(2))).to_string(),
+        "blend inherited, framebuffer binding 2"
+    );
+}
@@ -0,0 +1,99 @@
+use materialbin::{
+    builder::SamplerBuilder,
+    sampler_definition::{Filter, SamplerDefinition, SamplerState, TextureFormat, Wrap},
+    MinecraftVersion,
+};
+use scroll::Pread;
+
+const FORMATS: [&str; 16] = [
+    "R8", "R16F", "R32F", "R32UI", "RG8", "RG16F", "RG32F", "RGBA8", "RGBA16F", "RGBA32F",
+    "RGBA32UI", "RG11B10F", "RGB10A2", "D16", "D24S8", "D32F",
+];
+
+fn roundtrip(sampler: &SamplerDefinition, version: MinecraftVersion) -> SamplerDefinition {
+    let mut data = Vec::new();
+    sampler.write(&mut data, version).unwrap();
+    let parsed: SamplerDefinition = data.pread_with(0, version).unwrap();
+    let mut rewritten = Vec::new();
+    parsed.write(&mut rewritten, version).unwrap();
+    assert_eq!(rewritten, data);
+    parsed
+}
+
+#[test]
+fn texture_format_names_roundtrip() {
+    for name in FORMATS {
+        let format = TextureFormat::from_name(name);
+        assert!(!matches!(format, TextureFormat::Unknown(_)), "{name}");
+        assert_eq!(format.name(), name);
+        assert_eq!(format.to_string(), name);
+        assert_eq!(format.is_depth(), name.starts_with('D'), "{name}");
+    }
+    assert_eq!(TextureFormat::from_name("rgba8"), TextureFormat::RGBA8);
+    let unknown = TextureFormat::from_name("BC7");
+    assert_eq!(unknown, TextureFormat::Unknown("BC7".to_string()));
+    assert_eq!(unknown.name(), "BC7");
+    assert!(!unknown.is_depth());
+}
+
+#[test]
+fn texture_format_survives_writing() {
+    let version = MinecraftVersion::V1_21_20;
+    for format in FORMATS.map(TextureFormat::from_name) {
+        let mut sampler = SamplerBuilder::new(0).build(version).unwrap();
+        sampler.set_format(Some(format.clone()));
+        assert_eq!(roundtrip(&sampler, version).format(), Some(format));
+    }
+    let sampler = SamplerBuilder::new(0)
+        .texture_format("bc7")
+        .build(version)
+        .unwrap();
+    let parsed = roundtrip(&sampler, version);
+    assert_eq!(parsed.texture_format, "bc7");
+    assert_eq!(
+        parsed.format(),
+        Some(TextureFormat::Unknown("bc7".to_string()))
+    );
+    let sampler = SamplerBuilder::new(0).build(version).unwrap();
+    assert_eq!(roundtrip(&sampler, version).format(), None);
+}
+
+#[test]
+fn sampler_state_bits_roundtrip() {
+    for bits in 0..=u8::MAX {
+        let state = SamplerState::from(bits);
+        assert_eq!(u8::from(state), bits);
+        assert_eq!(state.other_bits & 0b11, 0);
+    }
+    assert_eq!(
+        SamplerState::from(0b11),
+        SamplerState::new(Filter::Bilinear, Wrap::Repeat)
+    );
+    assert_eq!(
+        SamplerState::from(0b10),
+        SamplerState::new(Filter::Point, Wrap::Repeat)
+    );
+    assert_eq!(
+        SamplerState::from(0b01),
+        SamplerState::new(Filter::Bilinear, Wrap::Clamp)
+    );
+    let mut state = SamplerState::new(Filter::Point, Wrap::Clamp);
+    state.other_bits = 0b11;
+    assert_eq!(
+        u8::from(state),
+        0,
+        "filter and wrap bits come from the fields"
+    );
+}
+
+#[test]
+fn sampler_state_survives_writing() {
+    let version = MinecraftVersion::V1_21_20;
+    for bits in [0, 1, 2, 3, 0x84] {
+        let mut sampler = SamplerBuilder::new(0).build(version).unwrap();
+        sampler.set_state(Some(SamplerState::from(bits)));
+        let parsed = roundtrip(&sampler, version);
+        assert_eq!(parsed.sampler_state, Some(bits));
+        assert_eq!(parsed.state(), Some(SamplerState::from(bits)));
+    }
+}
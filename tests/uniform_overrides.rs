@@ -0,0 +1,82 @@
+use materialbin::{
+    builder::MaterialBuilder,
+    material_set::MaterialSet,
+    property_field::{PropertyField, PropertyType},
+    uniform_override::LostOverride,
+    CompiledMaterialDefinition, MinecraftVersion, WriteError, BUILTINS_NAME,
+};
+use scroll::Pread;
+
+fn vec4(value: f32) -> PropertyField {
+    PropertyField {
+        field_type: PropertyType::Vec4,
+        num: 1,
+        vector_data: Some([value; 4].iter().flat_map(|f| f.to_le_bytes()).collect()),
+        matrix_data: None,
+    }
+}
+fn builtins() -> CompiledMaterialDefinition {
+    MaterialBuilder::new(BUILTINS_NAME)
+        .version(MinecraftVersion::V1_21_110)
+        .property("FogColor", vec4(0.5))
+        .build()
+        .unwrap()
+}
+fn overriding() -> CompiledMaterialDefinition {
+    MaterialBuilder::new("RenderChunk")
+        .version(MinecraftVersion::V1_21_110)
+        .uniform_override("u_FogColor", "FogColor")
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn overrides_need_inlining_before_older_versions() {
+    let mut data = Vec::new();
+    let result = overriding().write(&mut data, MinecraftVersion::V1_21_20);
+    assert!(matches!(result, Err(WriteError::Compat(_))), "{result:?}");
+    assert!(data.is_empty());
+}
+
+#[test]
+fn inlined_override_copies_the_builtin() {
+    let version = MinecraftVersion::V1_21_20;
+    let mut set = MaterialSet::new();
+    set.insert(builtins());
+    set.insert(overriding());
+    assert_eq!(set.inline_uniform_overrides(version), []);
+
+    let material = set.get("RenderChunk").unwrap();
+    assert_eq!(material.uniform_overrides, None);
+    let mut data = Vec::new();
+    material.write(&mut data, version).unwrap();
+    let parsed: CompiledMaterialDefinition = data.pread_with(0, version).unwrap();
+    assert_eq!(parsed.property_fields["u_FogColor"], vec4(0.5));
+}
+
+#[test]
+fn override_without_builtins_is_lost() {
+    let version = MinecraftVersion::V1_21_20;
+    let mut material = overriding();
+    let lost = material.clone().inline_uniform_overrides(version, None);
+    assert_eq!(
+        lost,
+        [LostOverride {
+            material: "RenderChunk".to_string(),
+            uniform: "u_FogColor".to_string(),
+            source: "FogColor".to_string(),
+        }]
+    );
+    let error = material
+        .try_inline_uniform_overrides(version, None)
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        WriteError::Compat(lost[0].to_string()).to_string()
+    );
+
+    let mut kept = overriding();
+    kept.try_inline_uniform_overrides(MinecraftVersion::V1_21_110, None)
+        .unwrap();
+    assert_eq!(kept, overriding());
+}
@@ -0,0 +1,292 @@
+use materialbin::{
+    builder::{MaterialBuilder, PassBuilder, SamplerBuilder, VariantBuilder},
+    pass::{ShaderCode, ShaderCodePlatform, ShaderStage},
+    CompiledMaterialDefinition, MinecraftVersion, ALL_VERSIONS,
+};
+use scroll::Pread;
+
+fn string(bytes: &mut Vec<u8>, s: &str) {
+    bytes.extend((s.len() as u32).to_le_bytes());
+    bytes.extend(s.as_bytes());
+}
+/// Material with one ESSL_310 fragment shader in the 26.0.24+ layout, encoded by hand.
+/// No 26.10.20 material is at hand to take a fixture from, so header 25 is only
+/// known to share the layout of 23 as far as this encoding goes
+fn fixture(header: u64) -> Vec<u8> {
+    const MAGIC: u64 = 0xA11DA1A;
+    let mut bytes = Vec::new();
+    bytes.extend(MAGIC.to_le_bytes());
+    string(&mut bytes, "RenderDragon.CompiledMaterialDefinition");
+    bytes.extend(header.to_le_bytes());
+    bytes.extend(0x4E4F4E45_u32.to_le_bytes());
+    string(&mut bytes, "Fixture");
+    bytes.push(0); // parent
+    bytes.push(0); // samplers
+    bytes.extend(0u16.to_le_bytes()); // properties
+    bytes.extend(0u16.to_le_bytes()); // uniform overrides
+    bytes.extend(1u16.to_le_bytes()); // passes
+    string(&mut bytes, "Opaque");
+    string(&mut bytes, "0"); // bitset
+    string(&mut bytes, ""); // fallback
+    bytes.push(0); // blend mode
+    bytes.extend(0u16.to_le_bytes()); // default flags
+    bytes.extend(0u32.to_le_bytes()); // framebuffer binding
+    bytes.extend(1u16.to_le_bytes()); // variants
+    bytes.push(1); // supported
+    bytes.extend(0u16.to_le_bytes()); // flags
+    bytes.extend(1u16.to_le_bytes()); // shader codes
+    string(&mut bytes, "Fragment");
+    string(&mut bytes, "ESSL_310");
+    bytes.push(1);
+    bytes.push(10);
+    bytes.extend(0u16.to_le_bytes()); // inputs
+    bytes.extend(7u64.to_le_bytes()); // source hash
+    bytes.extend(4u32.to_le_bytes());
+    bytes.extend([1, 2, 3, 4]);
+    bytes.extend(MAGIC.to_le_bytes());
+    bytes
+}
+/// 1.18.30 material whose pass has `bitset`, or the single placeholder byte in its
+/// place, as the 1.18.30 pass layout in `Pass::try_from_ctx` describes
+fn legacy_fixture(bitset: Option<&str>) -> Vec<u8> {
+    const MAGIC: u64 = 0xA11DA1A;
+    let mut bytes = Vec::new();
+    bytes.extend(MAGIC.to_le_bytes());
+    string(&mut bytes, "RenderDragon.CompiledMaterialDefinition");
+    bytes.extend(22u64.to_le_bytes());
+    bytes.extend(0x4E4F4E45_u32.to_le_bytes());
+    string(&mut bytes, "Legacy");
+    bytes.push(0); // parent
+    bytes.push(0); // samplers
+    bytes.extend(0u16.to_le_bytes()); // properties
+    bytes.extend(1u16.to_le_bytes()); // passes
+    string(&mut bytes, "Opaque");
+    match bitset {
+        Some(bitset) => string(&mut bytes, bitset),
+        None => bytes.push(0), // placeholder instead of the bitset
+    }
+    string(&mut bytes, ""); // fallback
+    bytes.push(0); // blend mode
+    bytes.extend(0u16.to_le_bytes()); // default flags
+    bytes.extend(1u16.to_le_bytes()); // variants
+    bytes.push(1); // supported
+    bytes.extend(0u16.to_le_bytes()); // flags
+    bytes.extend(1u16.to_le_bytes()); // shader codes
+    string(&mut bytes, "Fragment");
+    string(&mut bytes, "ESSL_310");
+    bytes.push(1);
+    bytes.push(9); // ESSL_310 before ESSL_100 got an ID
+    bytes.extend(0u16.to_le_bytes()); // inputs
+    bytes.extend(7u64.to_le_bytes()); // source hash
+    bytes.extend(4u32.to_le_bytes());
+    bytes.extend([1, 2, 3, 4]);
+    bytes.extend(MAGIC.to_le_bytes());
+    bytes
+}
+fn parse(
+    data: &[u8],
+    version: MinecraftVersion,
+) -> Result<CompiledMaterialDefinition, materialbin::MyError> {
+    data.pread_with(0, version)
+}
+
+#[test]
+fn newest_revision_fixture() {
+    let version = MinecraftVersion::V26_10_20;
+    let data = fixture(25);
+    let material = parse(&data, version).unwrap();
+    assert_eq!(material.version, 25);
+    let (stage, code) = material.passes["Opaque"].variants[0]
+        .shader_codes
+        .first()
+        .unwrap();
+    assert_eq!(stage.stage, ShaderStage::Fragment);
+    assert_eq!(stage.platform, ShaderCodePlatform::Essl310);
+    assert_eq!(code.bgfx_shader_data, [1, 2, 3, 4]);
+
+    let mut written = Vec::new();
+    material.write(&mut written, version).unwrap();
+    assert_eq!(written, data);
+}
+
+#[test]
+fn header_selects_revision() {
+    assert!(parse(&fixture(23), MinecraftVersion::V26_0_24).is_ok());
+    assert!(parse(&fixture(23), MinecraftVersion::V26_10_20).is_err());
+    assert!(parse(&fixture(25), MinecraftVersion::V26_0_24).is_err());
+    let detected = |header| -> Vec<_> {
+        ALL_VERSIONS
+            .into_iter()
+            .filter(|v| parse(&fixture(header), *v).is_ok())
+            .collect()
+    };
+    assert_eq!(detected(25), [MinecraftVersion::V26_10_20]);
+    // No layout is known for header 24
+    assert_eq!(detected(24), []);
+    for version in ALL_VERSIONS {
+        let error = parse(&fixture(24), version).unwrap_err().to_string();
+        assert_eq!(error, "Unsupported material header version 24");
+    }
+    let error = parse(&fixture(23), MinecraftVersion::V26_10_20).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Material header version 23 is not the one of 26.10.20"
+    );
+}
+
+#[test]
+fn converts_between_newest_revisions() {
+    let material = parse(&fixture(25), MinecraftVersion::V26_10_20).unwrap();
+    let mut written = Vec::new();
+    material
+        .write(&mut written, MinecraftVersion::V26_0_24)
+        .unwrap();
+    assert_eq!(written, fixture(23));
+}
+
+#[test]
+fn game_versions() {
+    let version = MinecraftVersion::from_game_version("26.10.20.1");
+    assert_eq!(version, Some(MinecraftVersion::V26_10_20));
+    assert_eq!(
+        MinecraftVersion::from_game_version("26.9.0"),
+        Some(MinecraftVersion::V26_0_24)
+    );
+    assert_eq!(MinecraftVersion::V26_10_20.to_string(), "26.10.20");
+    assert_eq!("26.10.20".parse(), Ok(MinecraftVersion::V26_10_20));
+    assert_eq!(MinecraftVersion::default(), MinecraftVersion::V26_0_24);
+}
+
+#[test]
+fn builder_derives_bitset_per_revision() {
+    for (version, bitset) in [
+        (MinecraftVersion::V1_18_30, "00001000000000"),
+        (MinecraftVersion::V1_21_20, "000010000000000"),
+    ] {
+        let material = built(version);
+        let pass = &material.passes["Opaque"];
+        assert_eq!(pass.bitset, bitset, "{version}");
+        let (stage, _) = pass.variants[0].shader_codes.first().unwrap();
+        assert_eq!(stage.stage_name, "Fragment");
+    }
+}
+
+fn built(version: MinecraftVersion) -> CompiledMaterialDefinition {
+    let code = ShaderCode {
+        shader_inputs: Default::default(),
+        source_hash: 3,
+        bgfx_shader_data: vec![5; 6],
+    };
+    MaterialBuilder::new("RenderChunk")
+        .version(version)
+        .sampler("s_MatTexture", SamplerBuilder::new(1))
+        .pass(
+            "Opaque",
+            PassBuilder::new().default_flag("Fancy", "On").variant(
+                VariantBuilder::new().flag("Fancy", "On").shader_code(
+                    ShaderStage::Fragment,
+                    ShaderCodePlatform::Essl310,
+                    code,
+                ),
+            ),
+        )
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn every_revision_roundtrips() {
+    for version in ALL_VERSIONS {
+        let material = built(version);
+        let mut data = Vec::new();
+        material.write(&mut data, version).unwrap();
+        let parsed = parse(&data, version).unwrap();
+        assert_eq!(parsed, material, "{version}");
+        let mut rewritten = Vec::new();
+        parsed.write(&mut rewritten, version).unwrap();
+        assert_eq!(rewritten, data, "{version}");
+    }
+}
+
+#[test]
+fn legacy_placeholder_bitset() {
+    let legacy = MinecraftVersion::V1_18_30;
+    let data = legacy_fixture(None);
+    let material = parse(&data, legacy).unwrap();
+    let pass = &material.passes["Opaque"];
+    assert_eq!(pass.bitset, "");
+    assert_eq!(pass.fallback, "");
+    let (stage, code) = pass.variants[0].shader_codes.first().unwrap();
+    assert_eq!(stage.platform, ShaderCodePlatform::Essl310);
+    assert_eq!(code.bgfx_shader_data, [1, 2, 3, 4]);
+
+    let mut written = Vec::new();
+    material.write(&mut written, legacy).unwrap();
+    assert_eq!(written, data);
+}
+
+#[test]
+fn upgrade_from_1_18_30_derives_bitset() {
+    let legacy = MinecraftVersion::V1_18_30;
+    let parsed = parse(&legacy_fixture(None), legacy).unwrap();
+    let modern = MinecraftVersion::V1_21_20;
+    let mut upgraded = Vec::new();
+    parsed.write(&mut upgraded, modern).unwrap();
+    let upgraded = parse(&upgraded, modern).unwrap();
+    // ESSL_310 has ID 10 of 15
+    assert_eq!(upgraded.passes["Opaque"].bitset, "000010000000000");
+}
+
+#[test]
+fn bitset_follows_platform_ids() {
+    let legacy = MinecraftVersion::V1_18_30;
+    let modern = MinecraftVersion::V1_21_20;
+    let material = built(modern);
+    // ESSL_310 has ID 10 of 15
+    assert_eq!(material.passes["Opaque"].bitset, "000010000000000");
+
+    // 1.18.30 stores bitsets of either numbering, so downgrading keeps it
+    let mut data = Vec::new();
+    material.write(&mut data, legacy).unwrap();
+    let downgraded = parse(&data, legacy).unwrap();
+    assert_eq!(downgraded.passes["Opaque"].bitset, "000010000000000");
+
+    // ID 9 of 14 before ESSL_100 got one
+    let legacy_material = built(legacy);
+    assert_eq!(legacy_material.passes["Opaque"].bitset, "00001000000000");
+    let mut data = Vec::new();
+    legacy_material.write(&mut data, modern).unwrap();
+    let upgraded = parse(&data, modern).unwrap();
+    assert_eq!(upgraded.passes, material.passes);
+}
+
+#[test]
+fn legacy_bitsets_roundtrip() {
+    let legacy = MinecraftVersion::V1_18_30;
+    for bitset in ["000010000000000", "00001000000000"] {
+        let data = legacy_fixture(Some(bitset));
+        let material = parse(&data, legacy).unwrap();
+        assert_eq!(material.passes["Opaque"].bitset, bitset);
+        let mut written = Vec::new();
+        material.write(&mut written, legacy).unwrap();
+        assert_eq!(written, data, "{bitset}");
+    }
+}
+
+#[test]
+fn malformed_legacy_bitsets_are_errors() {
+    let legacy = MinecraftVersion::V1_18_30;
+    // Lengths other than 14 and 15 aren't read as the placeholder byte
+    for (bitset, error) in [
+        ("0000", "bitset length: 4"),
+        ("0000100000000000", "bitset length: 16"),
+        ("0000100000000x", "bitset: \"0000100000000x\""),
+    ] {
+        let data = legacy_fixture(Some(bitset));
+        let message = parse(&data, legacy).unwrap_err().to_string();
+        assert!(message.contains(error), "{message}");
+    }
+    // 1.18.30 has no empty bitset string, its zero length is the placeholder
+    // and the rest of the pass no longer lines up
+    assert!(parse(&legacy_fixture(Some("")), legacy).is_err());
+}
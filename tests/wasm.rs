@@ -0,0 +1,102 @@
+//! Run in a wasm runtime with `wasm-bindgen-test-runner` as the wasm32 cargo runner,
+//! the success paths also run natively with `cargo test --features wasm`
+#![cfg(feature = "wasm")]
+
+use materialbin::{
+    builder::{MaterialBuilder, PassBuilder, SamplerBuilder, VariantBuilder},
+    pass::{ShaderCode, ShaderCodePlatform, ShaderStage},
+    wasm::{convert, detect_version, Material, Version},
+    MinecraftVersion,
+};
+use serde_json::Value;
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen_test::wasm_bindgen_test as test;
+
+fn material_bytes() -> Vec<u8> {
+    let code = ShaderCode {
+        shader_inputs: Default::default(),
+        source_hash: u64::MAX,
+        bgfx_shader_data: vec![0; 8],
+    };
+    let material = MaterialBuilder::new("RenderChunk")
+        .version(MinecraftVersion::V1_21_20)
+        .sampler("s_MatTexture", SamplerBuilder::new(0))
+        .pass(
+            "Opaque",
+            PassBuilder::new().default_flag("Fancy", "On").variant(
+                VariantBuilder::new().flag("Fancy", "On").shader_code(
+                    ShaderStage::Fragment,
+                    ShaderCodePlatform::Essl310,
+                    code,
+                ),
+            ),
+        )
+        .build()
+        .unwrap();
+    let mut data = Vec::new();
+    material
+        .write(&mut data, MinecraftVersion::V1_21_20)
+        .unwrap();
+    data
+}
+
+#[test]
+fn parse_and_write() {
+    let data = material_bytes();
+    assert_eq!(detect_version(&data), Some(Version::V1_21_20));
+    let material = Material::parse(&data, None).unwrap();
+    assert_eq!(material.version(), Version::V1_21_20);
+    assert_eq!(material.name(), "RenderChunk");
+    assert_eq!(material.write(None).unwrap(), data);
+}
+
+#[test]
+fn convert_roundtrip() {
+    let data = material_bytes();
+    let old = convert(&data, Version::V1_20_80).unwrap();
+    let material = Material::parse(&old, Some(Version::V1_20_80)).unwrap();
+    assert_eq!(material.write(Some(Version::V1_21_20)).unwrap(), data);
+}
+
+#[test]
+fn convert_roundtrip_1_18_30() {
+    let data = material_bytes();
+    let old = convert(&data, Version::V1_18_30).unwrap();
+    let material = Material::parse(&old, Some(Version::V1_18_30)).unwrap();
+    assert_eq!(material.write(Some(Version::V1_21_20)).unwrap(), data);
+}
+
+#[test]
+fn json_view() {
+    let material = Material::parse(&material_bytes(), None).unwrap();
+    let json: Value = serde_json::from_str(&material.to_json_string()).unwrap();
+    assert_eq!(json["name"], "RenderChunk");
+    assert_eq!(json["version"], "1.21.20");
+    assert_eq!(json["samplers"]["s_MatTexture"]["reg"], 0);
+    let code = &json["passes"]["Opaque"]["variants"][0]["shaderCodes"][0];
+    assert_eq!(code["platform"], "ESSL_310");
+    assert_eq!(code["stage"], "Fragment");
+    assert_eq!(code["sourceHash"], u64::MAX.to_string());
+    assert_eq!(code["size"], 8);
+}
+
+#[test]
+fn convert_drops_lost_overrides() {
+    let material = MaterialBuilder::new("RenderChunk")
+        .version(MinecraftVersion::V1_21_110)
+        .uniform_override("u_FogColor", "FogColor")
+        .build()
+        .unwrap();
+    let mut data = Vec::new();
+    material
+        .write(&mut data, MinecraftVersion::V1_21_110)
+        .unwrap();
+    let kept = convert(&data, Version::V1_21_110).unwrap();
+    assert_eq!(kept, data);
+    let old = convert(&data, Version::V1_21_20).unwrap();
+    let json = Material::parse(&old, Some(Version::V1_21_20))
+        .unwrap()
+        .to_json_string();
+    let json: Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(json["uniformOverrides"], Value::Null);
+}
@@ -0,0 +1,106 @@
+use materialbin::{
+    bgfx_shader::{BgfxShader, Uniform},
+    builder::{MaterialBuilder, PassBuilder, SamplerBuilder, VariantBuilder},
+    CompiledMaterialDefinition, MinecraftVersion, WriteError,
+};
+
+const VERSION: MinecraftVersion = MinecraftVersion::V1_21_20;
+
+fn material() -> CompiledMaterialDefinition {
+    MaterialBuilder::new("RenderChunk")
+        .version(VERSION)
+        .sampler("s_MatTexture", SamplerBuilder::new(0))
+        .pass(
+            "Opaque",
+            PassBuilder::new()
+                .default_flag("Fancy", "On")
+                .variant(VariantBuilder::new().flag("Fancy", "On")),
+        )
+        .build()
+        .unwrap()
+}
+/// Writing `material` fails with a limit error at `path` and leaves the writer empty
+fn assert_limit(material: &CompiledMaterialDefinition, path: &str, len: usize, max: usize) {
+    let mut output = Vec::new();
+    match material.write(&mut output, VERSION) {
+        Err(WriteError::Limit {
+            path: error_path,
+            len: error_len,
+            max: error_max,
+        }) => assert_eq!(
+            (error_path.as_str(), error_len, error_max),
+            (path, len, max)
+        ),
+        other => panic!("expected a limit error at {path}, got {other:?}"),
+    }
+    assert!(output.is_empty(), "{} bytes written", output.len());
+    assert!(material.validate_for_write(VERSION).is_err());
+    assert!(material.write_vec(VERSION).is_err());
+}
+
+#[test]
+fn too_many_samplers() {
+    let mut material = material();
+    let sampler = material.sampler_definitions[0].clone();
+    for index in 0..u8::MAX {
+        material
+            .sampler_definitions
+            .insert(format!("s_Extra{index}"), sampler.clone());
+    }
+    assert_limit(&material, "sampler_definitions", 256, 255);
+}
+
+#[test]
+fn too_many_passes() {
+    let mut material = material();
+    let pass = material.passes[0].clone();
+    for index in 0..u16::MAX {
+        material.passes.insert(format!("Pass{index}"), pass.clone());
+    }
+    assert_limit(&material, "passes", 65536, 65535);
+}
+
+#[test]
+fn too_many_variant_flags() {
+    let mut material = material();
+    let flags = &mut material.passes["Opaque"].variants[0].flags;
+    for index in 0..u16::MAX {
+        flags.insert(format!("Flag{index}"), "On".to_string());
+    }
+    assert_limit(&material, "passes[Opaque].variants[0].flags", 65536, 65535);
+}
+
+#[test]
+fn bgfx_uniform_name_too_long() {
+    let shader = BgfxShader {
+        magic: u32::from_le_bytes(*b"FSH\x0b"),
+        hash: 0,
+        uniforms: vec![Uniform {
+            name: "u".repeat(256),
+            utype: 0,
+            num: 1,
+            reg_index: 0,
+            reg_count: 1,
+        }],
+        code: b"void main() {}".to_vec(),
+        attributes: None,
+        size: None,
+    };
+    let mut output = Vec::new();
+    match shader.write(&mut output) {
+        Err(WriteError::Limit { path, len, max }) => {
+            assert_eq!((path.as_str(), len, max), ("uniforms[0].name", 256, 255))
+        }
+        other => panic!("expected a limit error, got {other:?}"),
+    }
+    assert!(output.is_empty(), "{} bytes written", output.len());
+}
+
+#[test]
+fn write_vec_matches_write() {
+    let material = material();
+    let mut output = Vec::new();
+    material.write(&mut output, VERSION).unwrap();
+    assert_eq!(material.write_vec(VERSION).unwrap(), output);
+    assert_eq!(material.serialized_size(VERSION).unwrap(), output.len());
+}